@@ -0,0 +1,38 @@
+/// How colors of translucent pixels are stored in DATA.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Colors are stored as they are, independently of alpha, the same as in PNG.
+    #[default]
+    Straight,
+    /// Colors are stored multiplied by alpha, which is how XNA and FNA games render them.
+    /// PNG colors get premultiplied when converted into DATA, and divided back when converted from it.
+    Premultiplied,
+}
+
+/// Multiplies colors of RGBA pixels (4 bytes per pixel) by their alpha, rounding to the nearest value.
+pub fn premultiply(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel[..3] {
+            // 255 is odd, so the exact result is never halfway between two values
+            *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Divides premultiplied colors of RGBA pixels (4 bytes per pixel) by their alpha, rounding to the nearest value.
+///
+/// Fully transparent pixels have no color left to recover, so they become transparent black.
+/// Colors exceeding their alpha, which premultiplied colors can't do, are clamped to 255.
+pub fn unpremultiply(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel[..3] {
+            *channel = match alpha {
+                0 => 0,
+                255 => *channel,
+                _ => ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8,
+            };
+        }
+    }
+}
@@ -0,0 +1,637 @@
+use crate::png::Png;
+use crate::data::DataImage;
+use crate::file::{scan_dir, ScanOptions};
+use crate::log;
+use crate::error::{Error, Result};
+use png::ColorType;
+use rayon::prelude::*;
+use std::fmt::{Display, Formatter};
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Problem with an atlas or its sprites, which prevents it from being sliced or packed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AtlasError {
+    /// META has a negative count of pages or subtextures.
+    NegativeCount(i16),
+    /// Count of pages or subtextures doesn't fit into META.
+    CountTooLarge(usize),
+    /// Length of a string in META is encoded with too many bytes.
+    MalformedStringLength,
+    /// String in META isn't valid UTF-8.
+    InvalidString,
+    /// Subtexture with the given path lies outside of its atlas page.
+    SubtextureOutsidePage(String),
+    /// Subtexture path isn't a plain relative path, so it could end up outside of the output directory.
+    InvalidSubtexturePath(String),
+    /// Output atlas path doesn't have a file name to name the pages after.
+    InvalidAtlasName(PathBuf),
+    /// Sprite path isn't valid UTF-8, so it can't be stored in META.
+    InvalidSpritePath(PathBuf),
+    /// Sprite is too big for the atlas format.
+    SpriteTooBig(PathBuf),
+    /// Sprite with the given path doesn't fit into a page of the maximum size.
+    SpriteDoesNotFit { sprite: String, max_page_size: usize },
+}
+
+impl Display for AtlasError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtlasError::NegativeCount(count) => write!(f, "Unexpected negative count value of {count}"),
+            AtlasError::CountTooLarge(count) => write!(f, "Count value of {count} doesn't fit into the atlas format"),
+            AtlasError::MalformedStringLength => write!(f, "Malformed string length"),
+            AtlasError::InvalidString => write!(f, "String isn't valid UTF-8"),
+            AtlasError::SubtextureOutsidePage(path) => write!(f, "Subtexture {path} lies outside of its atlas page"),
+            AtlasError::InvalidSubtexturePath(path) => write!(f, "Subtexture path is not a plain relative path: {path}"),
+            AtlasError::InvalidAtlasName(path) => {
+                write!(f, "Output atlas path doesn't have a valid file name: {}", path.display())
+            }
+            AtlasError::InvalidSpritePath(path) => write!(f, "Sprite path isn't valid UTF-8: {}", path.display()),
+            AtlasError::SpriteTooBig(path) => write!(f, "Sprite {} is too big for the atlas format", path.display()),
+            AtlasError::SpriteDoesNotFit { sprite, max_page_size } => {
+                write!(f, "Sprite {sprite} doesn't fit into a page of {max_page_size}x{max_page_size}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+/// Celeste texture atlas, as described by a binary `.meta` file.
+pub struct Atlas {
+    pub version: i32,
+    pub args: String,
+    pub hash: i32,
+    pub pages: Vec<AtlasPage>,
+}
+
+/// Single atlas page, stored in a separate `.data` file next to `.meta`.
+pub struct AtlasPage {
+    pub name: String,
+    pub subtextures: Vec<Subtexture>,
+}
+
+/// Named region of an atlas page.
+///
+/// Transparent borders are trimmed away from the stored region. The frame describes original
+/// sprite size, while negated frame offsets describe where the stored region is placed within it.
+pub struct Subtexture {
+    pub path: String,
+    pub x: i16,
+    pub y: i16,
+    pub width: i16,
+    pub height: i16,
+    pub frame_x: i16,
+    pub frame_y: i16,
+    pub frame_width: i16,
+    pub frame_height: i16,
+}
+
+impl Atlas {
+    pub fn read<R: Read>(input: &mut R) -> Result<Atlas> {
+        let version = read_i32(input)?;
+        let args = read_string(input)?;
+        let hash = read_i32(input)?;
+
+        let page_count = read_count(input)?;
+        let mut pages = Vec::with_capacity(page_count);
+        for _ in 0..page_count {
+            let name = read_string(input)?;
+
+            let subtexture_count = read_count(input)?;
+            let mut subtextures = Vec::with_capacity(subtexture_count);
+            for _ in 0..subtexture_count {
+                subtextures.push(Subtexture {
+                    // Paths may use Windows separators, normalize them
+                    path: read_string(input)?.replace('\\', "/"),
+                    x: read_i16(input)?,
+                    y: read_i16(input)?,
+                    width: read_i16(input)?,
+                    height: read_i16(input)?,
+                    frame_x: read_i16(input)?,
+                    frame_y: read_i16(input)?,
+                    frame_width: read_i16(input)?,
+                    frame_height: read_i16(input)?,
+                });
+            }
+
+            pages.push(AtlasPage { name, subtextures });
+        }
+
+        Ok(Atlas { version, args, hash, pages })
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> Result<()> {
+        write_i32(output, self.version)?;
+        write_string(output, &self.args)?;
+        write_i32(output, self.hash)?;
+
+        write_count(output, self.pages.len())?;
+        for page in &self.pages {
+            write_string(output, &page.name)?;
+
+            write_count(output, page.subtextures.len())?;
+            for subtexture in &page.subtextures {
+                write_string(output, &subtexture.path)?;
+                write_i16(output, subtexture.x)?;
+                write_i16(output, subtexture.y)?;
+                write_i16(output, subtexture.width)?;
+                write_i16(output, subtexture.height)?;
+                write_i16(output, subtexture.frame_x)?;
+                write_i16(output, subtexture.frame_y)?;
+                write_i16(output, subtexture.frame_width)?;
+                write_i16(output, subtexture.frame_height)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Slices atlas pages into individual PNG sprites, placed under their logical paths.
+pub fn slice(input: &Path, output: &Path) -> Result<()> {
+    log!("Input atlas: {}", input.display());
+    log!("Output directory: {}", output.display());
+
+    let atlas = match File::open(input) {
+        Ok(f) => Atlas::read(&mut BufReader::new(f))?,
+        Err(e) => return Err(Error::OpenInput { path: input.to_path_buf(), source: e }),
+    };
+
+    let input_dir = input.parent().unwrap();
+    for page in &atlas.pages {
+        let page_path = input_dir.join(format!("{}.data", page.name));
+        log!(debug: "Slicing atlas page {} into {} sprites", page_path.display(), page.subtextures.len());
+
+        let mut page_reader = match File::open(&page_path) {
+            Ok(f) => BufReader::new(f),
+            Err(e) => return Err(Error::OpenInput { path: page_path, source: e }),
+        };
+
+        let page_image = DataImage::read(&mut page_reader)?;
+        let page_width = page_image.width as usize;
+        let page_height = page_image.height as usize;
+        let page_rgba = page_image.to_rgba();
+
+        page.subtextures
+            .par_iter()
+            .map(|s| slice_subtexture(&page_rgba, page_width, page_height, s, output))
+            .collect::<Result<Vec<()>>>()?;
+    }
+
+    Ok(())
+}
+
+fn slice_subtexture(
+    page: &[u8],
+    page_width: usize,
+    page_height: usize,
+    subtexture: &Subtexture,
+    output: &Path,
+) -> Result<()> {
+    let x = subtexture.x as usize;
+    let y = subtexture.y as usize;
+    let width = subtexture.width as usize;
+    let height = subtexture.height as usize;
+    if subtexture.x < 0 || subtexture.y < 0 || subtexture.width < 0 || subtexture.height < 0
+        || x + width > page_width || y + height > page_height {
+        return Err(AtlasError::SubtextureOutsidePage(subtexture.path.clone()).into());
+    }
+
+    // Restore the original frame by placing the trimmed region back at its offset
+    let frame_width = subtexture.frame_width.max(0) as usize;
+    let frame_height = subtexture.frame_height.max(0) as usize;
+    let mut frame = vec![0; frame_width * frame_height * 4];
+    for row in 0..height {
+        let frame_row = row as isize - subtexture.frame_y as isize;
+        if frame_row < 0 || frame_row as usize >= frame_height {
+            continue;
+        }
+
+        for column in 0..width {
+            let frame_column = column as isize - subtexture.frame_x as isize;
+            if frame_column < 0 || frame_column as usize >= frame_width {
+                continue;
+            }
+
+            let page_offset = ((y + row) * page_width + x + column) * 4;
+            let frame_offset = (frame_row as usize * frame_width + frame_column as usize) * 4;
+            frame[frame_offset..frame_offset + 4].copy_from_slice(&page[page_offset..page_offset + 4]);
+        }
+    }
+
+    let output_path = subtexture_output_path(output, &subtexture.path)?;
+    let output_dir = output_path.parent().unwrap();
+    if let Err(e) = create_dir_all(output_dir) {
+        return Err(Error::CreateDirectory { path: output_dir.to_path_buf(), source: e });
+    }
+
+    let mut output_writer = match File::create(&output_path) {
+        Ok(f) => BufWriter::new(f),
+        Err(e) => return Err(Error::CreateOutput { path: output_path, source: e }),
+    };
+
+    write_png_rgba(&mut output_writer, frame_width, frame_height, &frame)
+}
+
+/// Packs a directory tree of PNG sprites into atlas pages and a META file describing them.
+///
+/// Pages are written next to the META file, named after it with a page number appended.
+pub fn pack(input: &Path, output: &Path, options: &PackOptions) -> Result<()> {
+    log!("Input directory: {}", input.display());
+    log!("Output atlas: {}", output.display());
+
+    let atlas_name = match output.file_stem().and_then(|s| s.to_str()) {
+        Some(name) => name.to_string(),
+        None => return Err(AtlasError::InvalidAtlasName(output.to_path_buf()).into()),
+    };
+    let output_dir = output.parent().unwrap();
+
+    let mut sprite_paths = Vec::new();
+    scan_dir(input, "png", &ScanOptions::default(), &mut sprite_paths)?;
+    log!("Found {} sprites", sprite_paths.len());
+
+    let mut sprites = sprite_paths
+        .par_iter()
+        .map(|p| load_sprite(input, p))
+        .collect::<Result<Vec<Sprite>>>()?;
+
+    // Place bigger sprites first, which results in tighter packing
+    sprites.sort_by(|a, b| {
+        let a_side = a.width.max(a.height);
+        let b_side = b.width.max(b.height);
+        b_side.cmp(&a_side).then_with(|| a.path.cmp(&b.path))
+    });
+
+    let pages = pack_sprites(&sprites, options)?;
+    log!("Packed sprites into {} pages", pages.len());
+
+    if let Err(e) = create_dir_all(output_dir) {
+        return Err(Error::CreateDirectory { path: output_dir.to_path_buf(), source: e });
+    }
+
+    let mut atlas = Atlas { version: 0, args: String::new(), hash: 0, pages: Vec::with_capacity(pages.len()) };
+    for (page_index, page) in pages.iter().enumerate() {
+        let page_name = format!("{atlas_name}{page_index}");
+        let page_path = output_dir.join(format!("{page_name}.data"));
+        log!(debug: "Writing atlas page {} with {} sprites", page_path.display(), page.placements.len());
+
+        // Compose the page from trimmed sprites
+        let mut page_rgba = vec![0; page.width * page.height * 4];
+        for placement in &page.placements {
+            let sprite = &sprites[placement.sprite];
+            for row in 0..sprite.height {
+                let sprite_offset = row * sprite.width * 4;
+                let page_offset = ((placement.y + row) * page.width + placement.x) * 4;
+                page_rgba[page_offset..page_offset + sprite.width * 4]
+                    .copy_from_slice(&sprite.rgba[sprite_offset..sprite_offset + sprite.width * 4]);
+            }
+        }
+
+        let page_image = DataImage::from_rgba(page.width as u32, page.height as u32, page_rgba)?;
+
+        let mut page_writer = match File::create(&page_path) {
+            Ok(f) => BufWriter::new(f),
+            Err(e) => return Err(Error::CreateOutput { path: page_path, source: e }),
+        };
+        page_image.write(&mut page_writer)?;
+
+        let subtextures = page.placements
+            .iter()
+            .map(|placement| {
+                let sprite = &sprites[placement.sprite];
+                Subtexture {
+                    path: sprite.path.clone(),
+                    x: placement.x as i16,
+                    y: placement.y as i16,
+                    width: sprite.width as i16,
+                    height: sprite.height as i16,
+                    frame_x: -(sprite.trim_x as i16),
+                    frame_y: -(sprite.trim_y as i16),
+                    frame_width: sprite.frame_width as i16,
+                    frame_height: sprite.frame_height as i16,
+                }
+            })
+            .collect();
+        atlas.pages.push(AtlasPage { name: page_name, subtextures });
+    }
+
+    let mut output_writer = match File::create(output) {
+        Ok(f) => BufWriter::new(f),
+        Err(e) => return Err(Error::CreateOutput { path: output.to_path_buf(), source: e }),
+    };
+    atlas.write(&mut output_writer)?;
+
+    Ok(())
+}
+
+/// Sprite loaded for packing, with transparent borders trimmed away.
+struct Sprite {
+    path: String,
+    rgba: Vec<u8>,
+    width: usize,
+    height: usize,
+    trim_x: usize,
+    trim_y: usize,
+    frame_width: usize,
+    frame_height: usize,
+}
+
+/// Page filled with sprites, referenced by their indices.
+struct PackedPage {
+    width: usize,
+    height: usize,
+    placements: Vec<Placement>,
+}
+
+struct Placement {
+    sprite: usize,
+    x: usize,
+    y: usize,
+}
+
+fn load_sprite(input: &Path, sprite_path: &Path) -> Result<Sprite> {
+    let relative_path = sprite_path.strip_prefix(input).unwrap_or(sprite_path).with_extension("");
+    let path = match relative_path.to_str() {
+        Some(p) => p.replace('\\', "/"),
+        None => return Err(AtlasError::InvalidSpritePath(sprite_path.to_path_buf()).into()),
+    };
+
+    let png = match File::open(sprite_path) {
+        Ok(f) => Png::load(&mut BufReader::new(f))?,
+        Err(e) => return Err(Error::OpenInput { path: sprite_path.to_path_buf(), source: e }),
+    };
+    png.check_palette()?;
+    let frame_rgba = png.as_chunk().rgba();
+    let frame_width = png.width;
+    let frame_height = png.height;
+    if frame_width > i16::MAX as usize || frame_height > i16::MAX as usize {
+        return Err(AtlasError::SpriteTooBig(sprite_path.to_path_buf()).into());
+    }
+
+    // Find bounds of non-transparent pixels
+    let is_opaque = |x: usize, y: usize| frame_rgba[(y * frame_width + x) * 4 + 3] != 0;
+    let rows: Vec<usize> = (0..frame_height).filter(|&y| (0..frame_width).any(|x| is_opaque(x, y))).collect();
+    let columns: Vec<usize> = (0..frame_width).filter(|&x| (0..frame_height).any(|y| is_opaque(x, y))).collect();
+    let (trim_x, trim_y, width, height) = match (rows.first(), rows.last(), columns.first(), columns.last()) {
+        (Some(&top), Some(&bottom), Some(&left), Some(&right)) => (left, top, right - left + 1, bottom - top + 1),
+        // Fully transparent sprite keeps its frame, but takes no space on the page
+        _ => (0, 0, 0, 0),
+    };
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in trim_y..trim_y + height {
+        let offset = (row * frame_width + trim_x) * 4;
+        rgba.extend_from_slice(&frame_rgba[offset..offset + width * 4]);
+    }
+
+    Ok(Sprite { path, rgba, width, height, trim_x, trim_y, frame_width, frame_height })
+}
+
+fn pack_sprites(sprites: &[Sprite], options: &PackOptions) -> Result<Vec<PackedPage>> {
+    let max_page_size = options.max_page_size.min(i16::MAX as usize);
+    let padding = options.padding;
+
+    let mut bins: Vec<MaxRects> = Vec::new();
+    let mut pages: Vec<PackedPage> = Vec::new();
+    for (sprite_index, sprite) in sprites.iter().enumerate() {
+        if sprite.width > max_page_size || sprite.height > max_page_size {
+            return Err(AtlasError::SpriteDoesNotFit { sprite: sprite.path.clone(), max_page_size }.into());
+        }
+
+        // Padding is added to the bottom and right of every sprite, so pages are enlarged to match
+        let width = sprite.width + padding;
+        let height = sprite.height + padding;
+
+        let existing = bins
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, b)| b.insert(width, height).map(|(x, y)| (i, x, y)));
+        let (page_index, x, y) = match existing {
+            Some(position) => position,
+            None => {
+                let mut bin = MaxRects::new(max_page_size + padding, max_page_size + padding);
+                let (x, y) = bin.insert(width, height).unwrap();
+                bins.push(bin);
+                pages.push(PackedPage { width: 0, height: 0, placements: Vec::new() });
+                (pages.len() - 1, x, y)
+            }
+        };
+
+        // Pages only grow as big as their contents need
+        let page = &mut pages[page_index];
+        page.width = page.width.max(x + sprite.width).max(1);
+        page.height = page.height.max(y + sprite.height).max(1);
+        page.placements.push(Placement { sprite: sprite_index, x, y });
+    }
+
+    Ok(pages)
+}
+
+/// Options controlling how sprites are packed into atlas pages.
+pub struct PackOptions {
+    /// Maximum width and height of a single page.
+    pub max_page_size: usize,
+    /// Empty space left between neighbouring sprites.
+    pub padding: usize,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        PackOptions { max_page_size: 4096, padding: 1 }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Rect {
+    fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.y >= self.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        other.x < self.x + self.width && self.x < other.x + other.width
+            && other.y < self.y + self.height && self.y < other.y + other.height
+    }
+}
+
+/// MaxRects bin packer, placing rectangles using the "best short side fit" heuristic.
+struct MaxRects {
+    free: Vec<Rect>,
+}
+
+impl MaxRects {
+    fn new(width: usize, height: usize) -> MaxRects {
+        MaxRects { free: vec![Rect { x: 0, y: 0, width, height }] }
+    }
+
+    fn insert(&mut self, width: usize, height: usize) -> Option<(usize, usize)> {
+        // Empty rectangles don't need any space
+        if width == 0 || height == 0 {
+            return Some((0, 0));
+        }
+
+        // Choose free space, which leaves the least of leftover space along either side
+        let best = self.free
+            .iter()
+            .filter(|f| f.width >= width && f.height >= height)
+            .min_by_key(|f| {
+                let leftover_width = f.width - width;
+                let leftover_height = f.height - height;
+                (leftover_width.min(leftover_height), leftover_width.max(leftover_height))
+            })?;
+        let placed = Rect { x: best.x, y: best.y, width, height };
+
+        // Split every free space overlapping the placed rectangle into up to four remaining parts
+        let mut free = Vec::with_capacity(self.free.len() + 4);
+        for f in &self.free {
+            if !f.intersects(&placed) {
+                free.push(*f);
+                continue;
+            }
+
+            if placed.x > f.x {
+                free.push(Rect { x: f.x, y: f.y, width: placed.x - f.x, height: f.height });
+            }
+            if placed.x + placed.width < f.x + f.width {
+                let x = placed.x + placed.width;
+                free.push(Rect { x, y: f.y, width: f.x + f.width - x, height: f.height });
+            }
+            if placed.y > f.y {
+                free.push(Rect { x: f.x, y: f.y, width: f.width, height: placed.y - f.y });
+            }
+            if placed.y + placed.height < f.y + f.height {
+                let y = placed.y + placed.height;
+                free.push(Rect { x: f.x, y, width: f.width, height: f.y + f.height - y });
+            }
+        }
+
+        // Drop free spaces fully covered by others
+        let mut pruned: Vec<Rect> = Vec::with_capacity(free.len());
+        for (i, f) in free.iter().enumerate() {
+            let covered = free.iter().enumerate().any(|(j, other)| {
+                i != j && other.contains(f) && (!f.contains(other) || j < i)
+            });
+            if !covered {
+                pruned.push(*f);
+            }
+        }
+        self.free = pruned;
+
+        Some((placed.x, placed.y))
+    }
+}
+
+fn write_png_rgba<W: Write>(output: &mut W, width: usize, height: usize, data: &[u8]) -> Result<()> {
+    let mut png_encoder = png::Encoder::new(output, width as u32, height as u32);
+    png_encoder.set_depth(png::BitDepth::Eight);
+    png_encoder.set_color(ColorType::Rgba);
+
+    let mut png_writer = png_encoder.write_header()?;
+    png_writer.write_image_data(data)?;
+
+    Ok(())
+}
+
+fn subtexture_output_path(output: &Path, subtexture_path: &str) -> Result<PathBuf> {
+    let relative_path = PathBuf::from(format!("{subtexture_path}.png"));
+
+    // Don't let atlas files write anywhere outside of the output directory
+    if !relative_path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(AtlasError::InvalidSubtexturePath(subtexture_path.to_string()).into());
+    }
+
+    Ok(output.join(relative_path))
+}
+
+#[inline]
+fn read_i16<R: Read>(input: &mut R) -> Result<i16> {
+    let mut buf = [0; 2];
+    input.read_exact(&mut buf)?;
+    Ok(i16::from_le_bytes(buf))
+}
+
+#[inline]
+fn read_i32<R: Read>(input: &mut R) -> Result<i32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+#[inline]
+fn read_count<R: Read>(input: &mut R) -> Result<usize> {
+    let count = read_i16(input)?;
+    if count < 0 {
+        return Err(AtlasError::NegativeCount(count).into());
+    }
+    Ok(count as usize)
+}
+
+/// Reads a string prefixed with its length as 7-bit encoded integer, as done by .NET `BinaryReader`.
+fn read_string<R: Read>(input: &mut R) -> Result<String> {
+    let mut len = 0usize;
+    let mut shift = 0;
+    loop {
+        let mut buf = [0];
+        input.read_exact(&mut buf)?;
+        len |= ((buf[0] & 0x7F) as usize) << shift;
+        if buf[0] & 0x80 == 0 {
+            break;
+        }
+
+        shift += 7;
+        if shift > 28 {
+            return Err(AtlasError::MalformedStringLength.into());
+        }
+    }
+
+    let mut buf = vec![0; len];
+    input.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|_| AtlasError::InvalidString.into())
+}
+
+#[inline]
+fn write_i16<W: Write>(output: &mut W, value: i16) -> Result<()> {
+    output.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+#[inline]
+fn write_i32<W: Write>(output: &mut W, value: i32) -> Result<()> {
+    output.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+#[inline]
+fn write_count<W: Write>(output: &mut W, count: usize) -> Result<()> {
+    match i16::try_from(count) {
+        Ok(count) => write_i16(output, count),
+        Err(_) => Err(AtlasError::CountTooLarge(count).into()),
+    }
+}
+
+/// Writes a string prefixed with its length as 7-bit encoded integer, as done by .NET `BinaryWriter`.
+fn write_string<W: Write>(output: &mut W, value: &str) -> Result<()> {
+    let mut len = value.len();
+    loop {
+        let byte = (len & 0x7F) as u8;
+        len >>= 7;
+        if len == 0 {
+            output.write_all(&[byte])?;
+            break;
+        }
+        output.write_all(&[byte | 0x80])?;
+    }
+
+    output.write_all(value.as_bytes())?;
+    Ok(())
+}
@@ -1,308 +1,265 @@
+use crate::alpha::{premultiply, unpremultiply, AlphaMode};
+use crate::data::{DataImage, DataLimits, DataRows, DataWriter, TARGET_CHUNK_SIZE};
+use crate::dither::Dither;
 use crate::log;
-use crate::png::{Png, PngChunk};
-use anyhow::{anyhow, Result};
+use crate::manifest::hash_bytes;
+use crate::png::{Png, PngRows};
+use crate::error::Result;
 use png::ColorType;
 use rayon::prelude::*;
 use std::io::{Read, Write};
 
-const TARGET_CHUNK_SIZE: usize = 0x10000;
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
 
-/// Converts DATA to PNG. Output is limited to 24-bit RGB or 32-bit RGBA.
-pub fn data_to_png<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
-    log!("Converting DATA into PNG...");
-
-    let width = read_u32(input)?;
-    let height = read_u32(input)?;
-    let has_alpha = read_bool(input)?;
-
-    log!("DATA image parameters: {width}x{height}, has alpha: {has_alpha}");
-
-    let output_data = if has_alpha {
-        // DATA format with alpha has variable sample size (2 or 5 bytes)
-        // Parallel processing is not supported
-        data_to_png_rgba(input, (width * height) as usize)?
-    } else {
-        // DATA format without alpha has uniform sample size (4 bytes)
-        // Process chunks in parallel
-
-        let mut input_data = Vec::new();
-        input.read_to_end(&mut input_data)?;
+/// Version of option fingerprints, to be bumped whenever the same options start producing different output.
+const FINGERPRINT_VERSION: u8 = 1;
 
-        let input_chunks: Vec<&[u8]> = input_data.chunks(TARGET_CHUNK_SIZE * 4).collect();
-        let output_chunks: Vec<Result<Vec<u8>>> = input_chunks
-            .par_iter()
-            .map(|c| data_to_png_chunk_rgb(c))
-            .collect();
+/// Image format supported for conversion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Data,
+    Png,
+}
 
-        let mut output_data = Vec::with_capacity((width * height * 3) as usize);
-        for chunk in output_chunks {
-            output_data.extend_from_slice(chunk?.as_slice());
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Data => "data",
+            Format::Png => "png",
         }
-        output_data
-    };
-
-    let mut png_encoder = png::Encoder::new(output, width, height);
-    png_encoder.set_depth(png::BitDepth::Eight);
-    png_encoder.set_color(if has_alpha { ColorType::Rgba } else { ColorType::Rgb });
-
-    let mut png_writer = png_encoder.write_header()?;
-    png_writer.write_image_data(&output_data)?;
+    }
 
-    Ok(())
+    /// Returns the format this one gets converted into.
+    pub fn target(&self) -> Format {
+        match self {
+            Format::Data => Format::Png,
+            Format::Png => Format::Data,
+        }
+    }
 }
 
-/// Converts PNG into DATA.
-pub fn png_to_data<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
-    log!("Converting PNG into DATA...");
-
-    let png = Png::load(input)?;
+/// Detects input format from its first bytes and total length.
+///
+/// DATA doesn't have a signature, so its header is checked for consistency with the input length instead.
+pub fn detect_format(header: &[u8], len: u64) -> Option<Format> {
+    if header.starts_with(&PNG_SIGNATURE) {
+        return Some(Format::Png);
+    }
 
-    let width = png.width;
-    let height = png.height;
-    let has_alpha = png.color_type == ColorType::Rgba || png.color_type == ColorType::GrayscaleAlpha;
-    let color_type_str = match png.color_type {
-        ColorType::Indexed => "Indexed",
-        ColorType::Grayscale => "Grayscale",
-        ColorType::GrayscaleAlpha => "Grayscale alpha",
-        ColorType::Rgb => "RGB",
-        ColorType::Rgba => "RGBA",
+    let (width, height, has_alpha) = match header {
+        [w0, w1, w2, w3, h0, h1, h2, h3, a, ..] if *a <= 1 => {
+            (u32::from_le_bytes([*w0, *w1, *w2, *w3]), u32::from_le_bytes([*h0, *h1, *h2, *h3]), *a == 1)
+        }
+        _ => return None,
     };
-    log!("PNG input: {}x{}, color type: {}, bit depth: {}", width, height, color_type_str, png.bit_depth as u8);
-
-    // Write image headers (width, height and alpha channel flag)
-    write_u32(output, width as u32)?;
-    write_u32(output, height as u32)?;
-    write_bool(output, has_alpha)?;
 
-    // Process PNG chunks in parallel
-    let output_chunks: Vec<Vec<u8>> = if has_alpha {
-        png
-            .chunks(TARGET_CHUNK_SIZE)
-            .par_iter()
-            .map(|c| png_to_data_chunk_rgba(c))
-            .collect()
+    // Every run takes 4 bytes without alpha, or between 2 and 5 bytes with alpha
+    // Each one covers between 1 and 255 pixels
+    let stream_len = len.checked_sub(9)?;
+    let pixel_count = width as u64 * height as u64;
+    let min_runs = pixel_count.div_ceil(255);
+    let max_runs = pixel_count;
+    let plausible = if has_alpha {
+        (min_runs * 2..=max_runs * 5).contains(&stream_len)
     } else {
-        png
-            .chunks(TARGET_CHUNK_SIZE)
-            .par_iter()
-            .map(|c| png_to_data_chunk_rgb(c))
-            .collect()
+        stream_len % 4 == 0 && (min_runs..=max_runs).contains(&(stream_len / 4))
     };
 
-    for chunk in output_chunks {
-        output.write_all(chunk.as_slice())?;
-    }
-
-    Ok(())
+    plausible.then_some(Format::Data)
 }
 
-fn data_to_png_chunk_rgb(input: &[u8]) -> Result<Vec<u8>> {
-    let mut output = Vec::new();
-
-    for i in 0..input.len() / 4 {
-        let input_offset = i * 4;
-
-        // Read RLE count
-        let rle_count = input[input_offset];
-        if rle_count == 0 {
-            return Err(anyhow!("Unexpected RLE count value of 0"));
-        }
-
-        // Read individual channel values
-        let b = input[input_offset + 1];
-        let g = input[input_offset + 2];
-        let r = input[input_offset + 3];
-
-        // Output the next span of same-colored pixels
-        for _ in 0..rle_count {
-            output.push(r);
-            output.push(g);
-            output.push(b);
-        }
+/// Converts input of the given format into the other one.
+pub fn convert_from<R: Read, W: Write>(
+    format: Format,
+    input: &mut R,
+    output: &mut W,
+    data_options: &DataToPngOptions,
+    png_options: &PngToDataOptions,
+) -> Result<()> {
+    match format {
+        Format::Data => data_to_png_with_options(input, output, data_options),
+        Format::Png => png_to_data_with_options(input, output, png_options),
     }
-
-    Ok(output)
 }
 
-fn data_to_png_rgba<R: Read>(input: &mut R, pixel_count: usize) -> Result<Vec<u8>> {
-    let mut output = vec![0; pixel_count * 4];
-
-    let mut pixel = 0;
-    while pixel < pixel_count {
-        // Read RLE count
-        let rle_count = read_u8(input)? as usize;
-        if rle_count == 0 {
-            return Err(anyhow!("Unexpected RLE count value of 0"));
-        }
-
-        // Read individual channel values
-        let r: u8;
-        let g: u8;
-        let b: u8;
-        let a = read_u8(input)?;
-        if a != 0 {
-            b = read_u8(input)?;
-            g = read_u8(input)?;
-            r = read_u8(input)?;
-        } else {
-            b = 0;
-            g = 0;
-            r = 0;
-        }
-
-        // Output the next span of same-colored pixels
-        for i in 0..rle_count {
-            let output_offset = (pixel + i) * 4;
-            output[output_offset + 0] = r;
-            output[output_offset + 1] = g;
-            output[output_offset + 2] = b;
-            output[output_offset + 3] = a;
-        }
+/// Options for converting DATA into PNG.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DataToPngOptions {
+    /// Limits on the size of DATA images being decoded.
+    pub limits: DataLimits,
+    /// Decode and encode the image row by row, instead of keeping all of it in memory.
+    pub streaming: bool,
+    /// How colors of translucent pixels are stored in DATA, which are always straight in PNG.
+    pub alpha_mode: AlphaMode,
+}
 
-        pixel += rle_count;
+impl DataToPngOptions {
+    /// Fingerprint of the options that affect the output, which limits and streaming don't.
+    pub fn fingerprint(&self) -> u64 {
+        hash_bytes(&[FINGERPRINT_VERSION, alpha_mode_byte(self.alpha_mode)])
     }
-
-    Ok(output)
 }
 
-fn png_to_data_chunk_rgb(input: &PngChunk) -> Vec<u8> {
-    let rgb = input.rgb();
-    let mut output = Vec::new();
-
-    let mut pixel = 0;
-    while pixel < input.len {
-        let offset = pixel * 3;
-        let pixel_rgb = &rgb[offset..offset + 3];
-
-        // Calculate RLE count by looking ahead at the next pixels
-        let mut rle_count = 1;
-        loop {
-            // Don't step out of bounds
-            if pixel + rle_count >= rle_count {
-                break;
-            }
-
-            // Compare with next pixel color
-            let next_offset = (pixel + rle_count) * 3;
-            let next_pixel_rgb = &rgb[next_offset..next_offset + 3];
-            if next_pixel_rgb != pixel_rgb {
-                break;
-            }
-
-            // Increment, but don't exceed maximum 8-bit value
-            rle_count += 1;
-            if rle_count == 0xFF {
-                break;
-            }
-        }
+/// Converts DATA to PNG. Output is limited to 24-bit RGB or 32-bit RGBA.
+pub fn data_to_png<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
+    data_to_png_with_options(input, output, &DataToPngOptions::default())
+}
 
-        // Extract individual channel values
-        let r = pixel_rgb[0];
-        let g = pixel_rgb[1];
-        let b = pixel_rgb[2];
+/// Converts DATA to PNG with the given options.
+pub fn data_to_png_with_options<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &DataToPngOptions,
+) -> Result<()> {
+    log!(debug: "Converting DATA into PNG...");
 
-        // Write RLE count and RGB channel values
-        write_u8(&mut output, rle_count as u8).unwrap();
-        write_u8(&mut output, b).unwrap();
-        write_u8(&mut output, g).unwrap();
-        write_u8(&mut output, r).unwrap();
+    if options.streaming {
+        let mut rows = DataRows::new(input, &options.limits)?;
+        log_data_parameters(rows.width, rows.height, rows.has_alpha);
 
-        pixel += rle_count;
+        write_png(output, rows.width, rows.height, rows.has_alpha, options.alpha_mode, |row| rows.read_row(row))?;
+        rows.finish()
+    } else {
+        let image = DataImage::read_with_limits(input, &options.limits)?;
+        log_data_parameters(image.width, image.height, image.has_alpha);
+
+        let mut pixel_rows = image.pixels.chunks_exact(image.pixels.len() / image.height as usize);
+        write_png(output, image.width, image.height, image.has_alpha, options.alpha_mode, |row| {
+            row.copy_from_slice(pixel_rows.next().unwrap());
+            Ok(())
+        })
     }
-
-    output
 }
 
-fn png_to_data_chunk_rgba(input: &PngChunk) -> Vec<u8> {
-    let rgba = input.rgba();
-    let mut output = Vec::new();
-
-    let mut pixel = 0;
-    while pixel < input.len {
-        let offset = pixel * 4;
-        let pixel_rgba = &rgba[offset..offset + 4];
-
-        // Calculate RLE count by looking ahead at the next pixels
-        let mut rle_count = 1;
-        loop {
-            // Don't step out of bounds
-            if pixel + rle_count >= rle_count {
-                break;
-            }
-
-            // Compare with next pixel color
-            let next_offset = (pixel + rle_count) * 4;
-            let next_pixel_rgba = &rgba[next_offset..next_offset + 4];
-            if next_pixel_rgba != pixel_rgba {
-                break;
-            }
-
-            // Increment, but don't exceed maximum 8-bit value
-            rle_count += 1;
-            if rle_count == 0xFF {
-                break;
-            }
-        }
+fn log_data_parameters(width: u32, height: u32, has_alpha: bool) {
+    log!(debug: "DATA image parameters: {width}x{height}, has alpha: {has_alpha}");
+}
 
-        // Extract individual channel values
-        let r = pixel_rgba[0];
-        let g = pixel_rgba[1];
-        let b = pixel_rgba[2];
-        let a = pixel_rgba[3];
+/// Writes 24-bit RGB or 32-bit RGBA PNG, taking pixels from the function one row at a time.
+/// Premultiplied colors of RGBA rows are divided by alpha first.
+fn write_png<W: Write, F: FnMut(&mut [u8]) -> Result<()>>(
+    output: &mut W,
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+    alpha_mode: AlphaMode,
+    mut next_row: F,
+) -> Result<()> {
+    let mut png_encoder = png::Encoder::new(output, width, height);
+    png_encoder.set_depth(png::BitDepth::Eight);
+    png_encoder.set_color(if has_alpha { ColorType::Rgba } else { ColorType::Rgb });
 
-        // Write RLE count and RGBA channel values
-        write_u8(&mut output, rle_count as u8).unwrap();
-        write_u8(&mut output, a).unwrap();
-        if a != 0 {
-            write_u8(&mut output, b).unwrap();
-            write_u8(&mut output, g).unwrap();
-            write_u8(&mut output, r).unwrap();
+    let mut png_writer = png_encoder.write_header()?;
+    let mut png_stream = png_writer.stream_writer()?;
+    let mut row = vec![0; width as usize * if has_alpha { 4 } else { 3 }];
+    for _ in 0..height {
+        next_row(&mut row)?;
+        if has_alpha && alpha_mode == AlphaMode::Premultiplied {
+            unpremultiply(&mut row);
         }
-
-        pixel += rle_count;
+        png_stream.write_all(&row)?;
     }
+    png_stream.finish()?;
+    png_writer.finish()?;
 
-    output
+    Ok(())
 }
 
-#[inline]
-fn read_bool<R: Read>(input: &mut R) -> Result<bool> {
-    let mut buf = [0];
-    input.read_exact(&mut buf)?;
-    Ok(buf[0] != 0)
+/// Options for converting PNG into DATA.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PngToDataOptions {
+    /// Dithering applied when reducing 16-bit PNG samples to 8 bits.
+    pub dither: Dither,
+    /// RGBA color used for palette indices without a palette entry, which are an error if not set.
+    pub palette_fallback: Option<[u8; 4]>,
+    /// Decode and encode the image row by row, instead of keeping all of it in memory.
+    /// Error diffusion dithering is then limited to a single row.
+    pub streaming: bool,
+    /// How colors of translucent pixels are stored in DATA, which are always straight in PNG.
+    pub alpha_mode: AlphaMode,
 }
 
-#[inline]
-fn read_u8<R: Read>(input: &mut R) -> Result<u8> {
-    let mut buf = [0];
-    input.read_exact(&mut buf)?;
-    Ok(buf[0])
+impl PngToDataOptions {
+    /// Fingerprint of the options that affect the output.
+    /// Streaming only does when error diffusion dithering gets limited to a single row by it.
+    pub fn fingerprint(&self) -> u64 {
+        let dither = match self.dither {
+            Dither::None => 0,
+            Dither::Ordered => 1,
+            Dither::FloydSteinberg => 2,
+        };
+        let streaming = self.streaming && self.dither == Dither::FloydSteinberg;
+        let mut bytes = vec![FINGERPRINT_VERSION, alpha_mode_byte(self.alpha_mode), dither, streaming as u8];
+        match self.palette_fallback {
+            None => bytes.push(0),
+            Some(color) => {
+                bytes.push(1);
+                bytes.extend(color);
+            }
+        }
+        hash_bytes(&bytes)
+    }
 }
 
-#[inline]
-fn read_u32<R: Read>(input: &mut R) -> Result<u32> {
-    let mut buf = [0; 4];
-    input.read_exact(&mut buf)?;
-    Ok(u32::from_le_bytes(buf))
+fn alpha_mode_byte(alpha_mode: AlphaMode) -> u8 {
+    match alpha_mode {
+        AlphaMode::Straight => 0,
+        AlphaMode::Premultiplied => 1,
+    }
 }
 
-#[inline]
-fn write_bool<W: Write>(output: &mut W, value: bool) -> Result<()> {
-    let buf = [value as u8];
-    output.write_all(&buf)?;
-    Ok(())
+/// Converts PNG into DATA.
+pub fn png_to_data<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<()> {
+    png_to_data_with_options(input, output, &PngToDataOptions::default())
 }
 
-#[inline]
-fn write_u8<W: Write>(output: &mut W, value: u8) -> Result<()> {
-    let buf = [value];
-    output.write_all(&buf)?;
-    Ok(())
+/// Converts PNG into DATA with the given options.
+pub fn png_to_data_with_options<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &PngToDataOptions,
+) -> Result<()> {
+    log!(debug: "Converting PNG into DATA...");
+
+    if options.streaming {
+        let mut rows = PngRows::new(input)?;
+        rows.png.dither = options.dither;
+        rows.png.palette_fallback = options.palette_fallback;
+        log_png_parameters(&rows.png);
+
+        let has_alpha = rows.png.has_alpha();
+        let premultiplied = has_alpha && options.alpha_mode == AlphaMode::Premultiplied;
+        let mut writer = DataWriter::new(output, rows.png.width as u32, rows.png.height as u32, has_alpha)?;
+        while let Some(row) = rows.next_row()? {
+            let mut pixels = if has_alpha { row.rgba() } else { row.rgb() };
+            if premultiplied {
+                premultiply(&mut pixels);
+            }
+            writer.write_pixels(&pixels)?;
+        }
+        writer.finish()
+    } else {
+        let mut png = Png::load(input)?;
+        png.dither = options.dither;
+        png.palette_fallback = options.palette_fallback;
+        png.check_palette()?;
+        log_png_parameters(&png);
+
+        let mut image = DataImage::from_png(&png);
+        if image.has_alpha && options.alpha_mode == AlphaMode::Premultiplied {
+            image.pixels.par_chunks_mut(TARGET_CHUNK_SIZE * 4).for_each(premultiply);
+        }
+        image.write(output)
+    }
 }
 
-#[inline]
-fn write_u32<W: Write>(output: &mut W, value: u32) -> Result<()> {
-    let buf = u32::to_le_bytes(value);
-    output.write_all(&buf)?;
-    Ok(())
+fn log_png_parameters(png: &Png) {
+    let color_type_str = match png.color_type {
+        ColorType::Indexed => "Indexed",
+        ColorType::Grayscale => "Grayscale",
+        ColorType::GrayscaleAlpha => "Grayscale alpha",
+        ColorType::Rgb => "RGB",
+        ColorType::Rgba => "RGBA",
+    };
+    log!(debug: "PNG input: {}x{}, color type: {}, bit depth: {}", png.width, png.height, color_type_str, png.bit_depth as u8);
 }
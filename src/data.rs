@@ -0,0 +1,572 @@
+use crate::png::Png;
+use crate::error::{Error, Result};
+use rayon::prelude::*;
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read, Write};
+
+pub(crate) const TARGET_CHUNK_SIZE: usize = 0x10000;
+const HEADER_LEN: usize = 9;
+
+/// Limits on images decoded from DATA, which keep hostile input from causing huge allocations.
+#[derive(Clone, Copy, Debug)]
+pub struct DataLimits {
+    /// Maximum width or height.
+    pub max_dimension: u32,
+    /// Maximum total number of pixels.
+    pub max_pixels: u64,
+}
+
+impl Default for DataLimits {
+    fn default() -> Self {
+        DataLimits { max_dimension: 16384, max_pixels: 8192 * 8192 }
+    }
+}
+
+impl DataLimits {
+    /// Checks whether an image of the given dimensions fits within the limits, and into memory at all.
+    pub(crate) fn allow(&self, width: u32, height: u32) -> bool {
+        let pixel_count = width as u64 * height as u64;
+        width <= self.max_dimension
+            && height <= self.max_dimension
+            && pixel_count <= self.max_pixels
+            && usize::try_from(pixel_count).is_ok()
+    }
+}
+
+/// Problem with DATA input, which prevents it from being decoded.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DataError {
+    /// Input ends at the given byte offset, before the image is complete.
+    UnexpectedEnd(u64),
+    /// Alpha channel flag has a value other than 0 or 1.
+    InvalidAlphaFlag(u8),
+    /// Image has zero width or height.
+    ZeroDimension,
+    /// Image dimensions exceed the limits.
+    TooLarge { width: u32, height: u32 },
+    /// Run at the given byte offset has RLE count of 0.
+    ZeroRunLength(u64),
+    /// Runs cover more than the given number of pixels in the image.
+    RunsExceedImage(u64),
+    /// Input has extra bytes after the last run.
+    TrailingBytes { offset: u64, count: u64 },
+}
+
+impl Display for DataError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataError::UnexpectedEnd(offset) => write!(f, "Unexpected end of DATA at byte {offset}"),
+            DataError::InvalidAlphaFlag(value) => write!(f, "Unexpected alpha channel flag value of {value}"),
+            DataError::ZeroDimension => write!(f, "Image has zero width or height"),
+            DataError::TooLarge { width, height } => write!(f, "Image of {width}x{height} exceeds the size limits"),
+            DataError::ZeroRunLength(offset) => write!(f, "Unexpected RLE count value of 0 at byte {offset}"),
+            DataError::RunsExceedImage(pixel_count) => {
+                write!(f, "RLE runs exceed the image size of {pixel_count} pixels")
+            }
+            DataError::TrailingBytes { offset, count } => {
+                write!(f, "Unexpected {count} bytes after the last run at byte {offset}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+/// Image in Celeste DATA format, decoded into 8-bit pixels.
+pub struct DataImage {
+    pub width: u32,
+    pub height: u32,
+    pub has_alpha: bool,
+    /// RGBA pixels (4 bytes per pixel) if the image has alpha, RGB pixels (3 bytes per pixel) otherwise.
+    pub pixels: Vec<u8>,
+}
+
+/// Decoder of DATA, which produces one row of pixels at a time instead of the whole image.
+///
+/// Input is read in small pieces, so it's best to have it buffered.
+pub struct DataRows<R: Read> {
+    pub width: u32,
+    pub height: u32,
+    pub has_alpha: bool,
+    input: R,
+    /// Byte offset from the start of the input.
+    offset: u64,
+    /// Remaining pixels of the current run, which may continue into the next row.
+    run_len: usize,
+    run_color: [u8; 4],
+}
+
+impl<R: Read> DataRows<R> {
+    /// Reads image headers, leaving the pixels to be read row by row.
+    pub fn new(mut input: R, limits: &DataLimits) -> Result<DataRows<R>> {
+        let (width, height, has_alpha) = read_header(&mut input, limits)?;
+        Ok(DataRows { width, height, has_alpha, input, offset: HEADER_LEN as u64, run_len: 0, run_color: [0; 4] })
+    }
+
+    /// Returns the length of a single row in bytes.
+    pub fn row_len(&self) -> usize {
+        self.width as usize * if self.has_alpha { 4 } else { 3 }
+    }
+
+    /// Decodes the next row into RGBA pixels if the image has alpha, RGB pixels otherwise.
+    pub fn read_row(&mut self, row: &mut [u8]) -> Result<()> {
+        let pixel_size = if self.has_alpha { 4 } else { 3 };
+        let mut pixels = row.chunks_exact_mut(pixel_size);
+        let mut remaining = self.width as usize;
+        while remaining > 0 {
+            if self.run_len == 0 {
+                self.read_run()?;
+            }
+
+            let len = self.run_len.min(remaining);
+            for pixel in pixels.by_ref().take(len) {
+                pixel.copy_from_slice(&self.run_color[..pixel_size]);
+            }
+            self.run_len -= len;
+            remaining -= len;
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure the last run ends together with the image, and nothing follows it.
+    pub fn finish(mut self) -> Result<()> {
+        if self.run_len > 0 {
+            return Err(DataError::RunsExceedImage(self.width as u64 * self.height as u64).into());
+        }
+
+        let count = std::io::copy(&mut self.input, &mut std::io::sink())?;
+        if count > 0 {
+            return Err(DataError::TrailingBytes { offset: self.offset, count }.into());
+        }
+
+        Ok(())
+    }
+
+    fn read_run(&mut self) -> Result<()> {
+        // Read RLE count and alpha value (if present)
+        let run_offset = self.offset;
+        let mut sample = [0; 5];
+        let sample_len = if self.has_alpha { 2 } else { 4 };
+        self.read_sample(&mut sample[..sample_len])?;
+        if self.has_alpha && sample[1] != 0 {
+            self.read_sample(&mut sample[2..])?;
+        }
+
+        if sample[0] == 0 {
+            return Err(DataError::ZeroRunLength(run_offset).into());
+        }
+
+        // Channel values are stored in reverse order, color is omitted for fully transparent pixels
+        self.run_len = sample[0] as usize;
+        self.run_color = if self.has_alpha {
+            [sample[4], sample[3], sample[2], sample[1]]
+        } else {
+            [sample[3], sample[2], sample[1], 255]
+        };
+
+        Ok(())
+    }
+
+    fn read_sample(&mut self, buf: &mut [u8]) -> Result<()> {
+        let len = read_fully(&mut self.input, buf)?;
+        self.offset += len as u64;
+        if len < buf.len() {
+            return Err(DataError::UnexpectedEnd(self.offset).into());
+        }
+        Ok(())
+    }
+}
+
+/// Encoder of DATA, which takes pixels in pieces of any size instead of the whole image.
+pub struct DataWriter<W: Write> {
+    output: W,
+    has_alpha: bool,
+    /// Pixels not yet covered by runs.
+    remaining: u64,
+    /// Last run, which may still continue into the next pixels.
+    pending: Option<Run>,
+}
+
+impl<W: Write> DataWriter<W> {
+    /// Writes image headers (width, height and alpha channel flag), leaving the pixels to be written later.
+    pub fn new(mut output: W, width: u32, height: u32, has_alpha: bool) -> Result<DataWriter<W>> {
+        write_u32(&mut output, width)?;
+        write_u32(&mut output, height)?;
+        write_bool(&mut output, has_alpha)?;
+
+        Ok(DataWriter { output, has_alpha, remaining: width as u64 * height as u64, pending: None })
+    }
+
+    /// Encodes the next pixels, which are RGBA if the image has alpha, RGB otherwise.
+    pub fn write_pixels(&mut self, pixels: &[u8]) -> Result<()> {
+        let pixel_size = if self.has_alpha { 4 } else { 3 };
+        for run in find_runs(pixels, pixel_size) {
+            self.push_run(run)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last run, making sure it ends together with the image.
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(pending_run) = self.pending.take() {
+            write_run(&mut self.output, &pending_run, self.has_alpha)?;
+        }
+
+        if self.remaining > 0 {
+            return Err(Error::MissingPixels(self.remaining));
+        }
+
+        Ok(())
+    }
+
+    fn push_run(&mut self, run: Run) -> Result<()> {
+        if run.len as u64 > self.remaining {
+            return Err(Error::ExtraPixels { count: run.len as u64, remaining: self.remaining });
+        }
+        self.remaining -= run.len as u64;
+
+        match self.pending.as_mut() {
+            Some(pending_run) if pending_run.color == run.color => pending_run.len += run.len,
+            _ => {
+                if let Some(pending_run) = self.pending.replace(run) {
+                    write_run(&mut self.output, &pending_run, self.has_alpha)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Span of same-colored pixels, which may be longer than a single RLE count allows.
+struct Run {
+    len: usize,
+    color: [u8; 4],
+}
+
+/// Part of DATA input with known boundaries, which can be decoded independently.
+struct DataChunk<'a> {
+    data: &'a [u8],
+    pixel_count: usize,
+}
+
+impl DataImage {
+    /// Decodes an image from DATA format, using default limits.
+    pub fn read<R: Read>(input: &mut R) -> Result<DataImage> {
+        DataImage::read_with_limits(input, &DataLimits::default())
+    }
+
+    /// Decodes an image from DATA format, failing with [`DataError`] on malformed input.
+    pub fn read_with_limits<R: Read>(input: &mut R, limits: &DataLimits) -> Result<DataImage> {
+        let (width, height, has_alpha) = read_header(input, limits)?;
+        let pixel_count = width as usize * height as usize;
+
+        // Every run covers at least one pixel, so anything longer than that can't be valid
+        let max_sample_size = if has_alpha { 5 } else { 4 };
+        let max_input_len = pixel_count as u64 * max_sample_size;
+        let mut input_data = Vec::new();
+        input.take(max_input_len + 1).read_to_end(&mut input_data)?;
+
+        // Samples have variable size with alpha (2 or 5 bytes), and uniform size without it (4 bytes)
+        // Either way, find chunk boundaries with a quick sequential scan, then process chunks in parallel
+        let input_chunks = match index_chunks(&input_data, pixel_count, has_alpha) {
+            Ok(chunks) => chunks,
+            // Only part of the stream was read, so count whatever follows it too
+            Err(DataError::TrailingBytes { offset, count }) => {
+                let count = count + std::io::copy(input, &mut std::io::sink())?;
+                return Err(DataError::TrailingBytes { offset, count }.into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let pixel_size = if has_alpha { 4 } else { 3 };
+        let mut pixels = vec![0; pixel_count * pixel_size];
+        let mut output_chunks: Vec<&mut [u8]> = Vec::with_capacity(input_chunks.len());
+        let mut output_rest = pixels.as_mut_slice();
+        for chunk in &input_chunks {
+            let (output_chunk, rest) = output_rest.split_at_mut(chunk.pixel_count * pixel_size);
+            output_chunks.push(output_chunk);
+            output_rest = rest;
+        }
+
+        output_chunks
+            .into_par_iter()
+            .zip(input_chunks.par_iter())
+            .for_each(|(o, c)| if has_alpha { decode_chunk_rgba(c.data, o) } else { decode_chunk_rgb(c.data, o) });
+
+        Ok(DataImage { width, height, has_alpha, pixels })
+    }
+
+    /// Encodes the image into DATA format.
+    pub fn write<W: Write>(&self, output: &mut W) -> Result<()> {
+        let pixel_size = self.pixel_size();
+        let expected_len = self.width as usize * self.height as usize * pixel_size;
+        if self.pixels.len() != expected_len {
+            let (width, height) = (self.width as usize, self.height as usize);
+            return Err(Error::PixelDataLength { width, height, expected: expected_len, actual: self.pixels.len() });
+        }
+
+        let mut writer = DataWriter::new(output, self.width, self.height, self.has_alpha)?;
+
+        // Find runs of same-colored pixels in parallel
+        let chunk_runs: Vec<Vec<Run>> = self.pixels
+            .par_chunks(TARGET_CHUNK_SIZE * pixel_size)
+            .map(|c| find_runs(c, pixel_size))
+            .collect();
+
+        // Runs may continue across chunk boundaries, so they are merged before being written
+        for run in chunk_runs.into_iter().flatten() {
+            writer.push_run(run)?;
+        }
+
+        writer.finish()
+    }
+
+    /// Creates an image from PNG, keeping alpha channel only if PNG has transparency.
+    /// Palette indices without a palette entry aren't rejected here, so the palette should be checked beforehand.
+    pub fn from_png(png: &Png) -> DataImage {
+        let has_alpha = png.has_alpha();
+
+        // Process PNG chunks in parallel
+        let pixel_chunks: Vec<Vec<u8>> = png
+            .chunks(TARGET_CHUNK_SIZE)
+            .par_iter()
+            .map(|c| if has_alpha { c.rgba() } else { c.rgb() })
+            .collect();
+
+        DataImage {
+            width: png.width as u32,
+            height: png.height as u32,
+            has_alpha,
+            pixels: pixel_chunks.concat(),
+        }
+    }
+
+    /// Creates an image from RGBA pixels, keeping alpha channel only if any pixel isn't fully opaque.
+    pub fn from_rgba(width: u32, height: u32, rgba: Vec<u8>) -> Result<DataImage> {
+        let expected_len = width as usize * height as usize * 4;
+        if rgba.len() != expected_len {
+            let (width, height) = (width as usize, height as usize);
+            return Err(Error::PixelDataLength { width, height, expected: expected_len, actual: rgba.len() });
+        }
+
+        let has_alpha = rgba.par_chunks_exact(4).any(|p| p[3] != 255);
+        let pixels = if has_alpha {
+            rgba
+        } else {
+            rgba.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect()
+        };
+
+        Ok(DataImage { width, height, has_alpha, pixels })
+    }
+
+    /// Returns pixels in RGBA format (4 bytes per pixel).
+    pub fn to_rgba(&self) -> Vec<u8> {
+        if self.has_alpha {
+            self.pixels.clone()
+        } else {
+            self.pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect()
+        }
+    }
+
+    fn pixel_size(&self) -> usize {
+        if self.has_alpha { 4 } else { 3 }
+    }
+}
+
+fn index_chunks(input: &[u8], pixel_count: usize, has_alpha: bool) -> std::result::Result<Vec<DataChunk<'_>>, DataError> {
+    let mut chunks = Vec::new();
+
+    let mut chunk_offset = 0;
+    let mut chunk_pixel = 0;
+    let mut offset = 0;
+    let mut pixel = 0;
+    while pixel < pixel_count {
+        // Read RLE count and alpha value (if present), skip over color
+        let (rle_count, a) = match input.get(offset..offset + 2) {
+            Some(&[rle_count, a]) => (rle_count as usize, a),
+            _ => return Err(DataError::UnexpectedEnd(stream_offset(input.len()))),
+        };
+        if rle_count == 0 {
+            return Err(DataError::ZeroRunLength(stream_offset(offset)));
+        }
+
+        offset += if !has_alpha { 4 } else if a != 0 { 5 } else { 2 };
+        pixel += rle_count;
+
+        // Close the chunk once it's big enough
+        if pixel - chunk_pixel >= TARGET_CHUNK_SIZE || pixel >= pixel_count {
+            if offset > input.len() {
+                return Err(DataError::UnexpectedEnd(stream_offset(input.len())));
+            }
+            if pixel > pixel_count {
+                return Err(DataError::RunsExceedImage(pixel_count as u64));
+            }
+
+            let data = &input[chunk_offset..offset];
+            chunks.push(DataChunk { data, pixel_count: pixel - chunk_pixel });
+            chunk_offset = offset;
+            chunk_pixel = pixel;
+        }
+    }
+
+    if offset < input.len() {
+        return Err(DataError::TrailingBytes { offset: stream_offset(offset), count: (input.len() - offset) as u64 });
+    }
+
+    Ok(chunks)
+}
+
+fn decode_chunk_rgb(input: &[u8], output: &mut [u8]) {
+    let mut output_offset = 0;
+    for sample in input.chunks_exact(4) {
+        // Read RLE count and individual channel values
+        let rle_count = sample[0] as usize;
+        let (b, g, r) = (sample[1], sample[2], sample[3]);
+
+        // Output the next span of same-colored pixels
+        for pixel in output[output_offset..output_offset + rle_count * 3].chunks_exact_mut(3) {
+            pixel.copy_from_slice(&[r, g, b]);
+        }
+
+        output_offset += rle_count * 3;
+    }
+}
+
+fn decode_chunk_rgba(input: &[u8], output: &mut [u8]) {
+    let mut input_offset = 0;
+    let mut output_offset = 0;
+    while output_offset < output.len() {
+        // Read RLE count
+        let rle_count = input[input_offset] as usize;
+
+        // Read individual channel values
+        let a = input[input_offset + 1];
+        let (r, g, b) = if a != 0 {
+            let b = input[input_offset + 2];
+            let g = input[input_offset + 3];
+            let r = input[input_offset + 4];
+            input_offset += 5;
+            (r, g, b)
+        } else {
+            input_offset += 2;
+            (0, 0, 0)
+        };
+
+        // Output the next span of same-colored pixels
+        for pixel in output[output_offset..output_offset + rle_count * 4].chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[r, g, b, a]);
+        }
+
+        output_offset += rle_count * 4;
+    }
+}
+
+/// Converts an offset within the pixel stream into an offset within the whole DATA input.
+fn stream_offset(offset: usize) -> u64 {
+    (HEADER_LEN + offset) as u64
+}
+
+fn find_runs(pixels: &[u8], pixel_size: usize) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+
+    for pixel in pixels.chunks_exact(pixel_size) {
+        let color = match *pixel {
+            [r, g, b] => [r, g, b, 255],
+            // Fully transparent pixels don't store color, so they all belong to the same run
+            [_, _, _, 0] => [0, 0, 0, 0],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        };
+
+        // Extend the current run or start a new one, leaving the RLE count limit for later
+        match runs.last_mut() {
+            Some(run) if run.color == color => run.len += 1,
+            _ => runs.push(Run { len: 1, color }),
+        }
+    }
+
+    runs
+}
+
+fn write_run<W: Write>(output: &mut W, run: &Run, has_alpha: bool) -> Result<()> {
+    let [r, g, b, a] = run.color;
+
+    // Channel values are stored in reverse order, color is omitted for fully transparent pixels
+    let mut buf = [0; 5];
+    let sample = if !has_alpha {
+        buf[1..4].copy_from_slice(&[b, g, r]);
+        &mut buf[..4]
+    } else if a != 0 {
+        buf[1..5].copy_from_slice(&[a, b, g, r]);
+        &mut buf[..5]
+    } else {
+        &mut buf[..2]
+    };
+
+    let mut remaining = run.len;
+    while remaining > 0 {
+        // Split long runs, as RLE count can't exceed maximum 8-bit value
+        let rle_count = remaining.min(0xFF);
+        sample[0] = rle_count as u8;
+        output.write_all(sample)?;
+        remaining -= rle_count;
+    }
+
+    Ok(())
+}
+
+/// Reads image headers (width, height and alpha channel flag), making sure the image fits within the limits.
+pub(crate) fn read_header<R: Read>(input: &mut R, limits: &DataLimits) -> Result<(u32, u32, bool)> {
+    let mut header = [0; HEADER_LEN];
+    let len = read_fully(input, &mut header)?;
+    if len < HEADER_LEN {
+        return Err(DataError::UnexpectedEnd(len as u64).into());
+    }
+
+    let width = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    let has_alpha = match header[8] {
+        0 => false,
+        1 => true,
+        value => return Err(DataError::InvalidAlphaFlag(value).into()),
+    };
+
+    if width == 0 || height == 0 {
+        return Err(DataError::ZeroDimension.into());
+    }
+    if !limits.allow(width, height) {
+        return Err(DataError::TooLarge { width, height }.into());
+    }
+
+    Ok((width, height, has_alpha))
+}
+
+/// Reads until the buffer is full or the input ends, returning the number of bytes read.
+fn read_fully<R: Read>(input: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match input.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(len)
+}
+
+#[inline]
+fn write_bool<W: Write>(output: &mut W, value: bool) -> Result<()> {
+    let buf = [value as u8];
+    output.write_all(&buf)?;
+    Ok(())
+}
+
+#[inline]
+fn write_u32<W: Write>(output: &mut W, value: u32) -> Result<()> {
+    let buf = u32::to_le_bytes(value);
+    output.write_all(&buf)?;
+    Ok(())
+}
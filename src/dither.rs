@@ -0,0 +1,95 @@
+use std::mem::swap;
+
+/// Dithering applied when reducing 16-bit samples to 8 bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// No dithering, samples are rounded to the nearest 8-bit value.
+    #[default]
+    None,
+    /// Ordered dithering with a 4x4 Bayer matrix.
+    Ordered,
+    /// Floyd–Steinberg error diffusion.
+    /// Error doesn't spread between chunks, so they can still be processed in parallel.
+    FloydSteinberg,
+}
+
+const BAYER_4X4: [[u32; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+/// Reduces big-endian 16-bit samples into 8 bits with dithering.
+///
+/// Dithering depends on pixel positions, so the first pixel's index within the image is required
+/// along with image width.
+pub fn dither_sixteen_bit(data: &[u8], samples: usize, width: usize, start: usize, dither: Dither) -> Vec<u8> {
+    match dither {
+        Dither::None => round_sixteen_bit(data),
+        Dither::Ordered => dither_ordered(data, samples, width, start),
+        Dither::FloydSteinberg => dither_floyd_steinberg(data, samples, width, start),
+    }
+}
+
+/// Reduces big-endian 16-bit samples into 8 bits, rounding to the nearest value.
+pub fn round_sixteen_bit(data: &[u8]) -> Vec<u8> {
+    data.chunks_exact(2)
+        .map(|s| ((u16::from_be_bytes([s[0], s[1]]) as u32 * 255 + 32895) >> 16) as u8)
+        .collect()
+}
+
+fn dither_ordered(data: &[u8], samples: usize, width: usize, start: usize) -> Vec<u8> {
+    let mut output = vec![0; data.len() / 2];
+
+    for (i, sample) in data.chunks_exact(2).enumerate() {
+        let pixel = start + i / samples;
+        let x = pixel % width;
+        let y = pixel / width;
+
+        // Threshold is placed in the middle of its matrix cell, scaled to the 16-bit range
+        let threshold = (2 * BAYER_4X4[y % 4][x % 4] + 1) * 0xFFFF / 32;
+        let value = u16::from_be_bytes([sample[0], sample[1]]) as u32;
+        output[i] = ((value * 255 + threshold) / 0xFFFF).min(255) as u8;
+    }
+
+    output
+}
+
+fn dither_floyd_steinberg(data: &[u8], samples: usize, width: usize, start: usize) -> Vec<u8> {
+    let mut output = vec![0; data.len() / 2];
+    let pixel_count = output.len() / samples;
+
+    // Errors accumulated for the current and the next row, multiplied by 16
+    // Rows are padded by a pixel on both sides, so edge pixels don't need special treatment
+    let mut current_errors = vec![0i64; (width + 2) * samples];
+    let mut next_errors = vec![0i64; (width + 2) * samples];
+
+    for pixel in 0..pixel_count {
+        let x = (start + pixel) % width;
+        if x == 0 && pixel > 0 {
+            swap(&mut current_errors, &mut next_errors);
+            next_errors.fill(0);
+        }
+
+        for channel in 0..samples {
+            let offset = pixel * samples + channel;
+            let value = u16::from_be_bytes([data[offset * 2], data[offset * 2 + 1]]) as i64;
+
+            // Values are kept in 8-bit range, scaled by 0xFFFF to avoid losing precision
+            let error_offset = (x + 1) * samples + channel;
+            let wanted = value * 255 + current_errors[error_offset] / 16;
+            let quantized = (wanted + 0x7FFF).div_euclid(0xFFFF).clamp(0, 255);
+            output[offset] = quantized as u8;
+
+            // Spread the error to the right and to the row below
+            let error = wanted - quantized * 0xFFFF;
+            current_errors[error_offset + samples] += error * 7;
+            next_errors[error_offset - samples] += error * 3;
+            next_errors[error_offset] += error * 5;
+            next_errors[error_offset + samples] += error;
+        }
+    }
+
+    output
+}
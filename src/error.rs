@@ -0,0 +1,194 @@
+use crate::atlas::AtlasError;
+use crate::data::DataError;
+use crate::png::PngError;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error of converting images and files.
+#[derive(Debug)]
+pub enum Error {
+    /// DATA input can't be decoded.
+    Data(DataError),
+    /// PNG input can't be decoded, or PNG output can't be encoded.
+    Png(PngError),
+    /// Atlas can't be sliced or packed.
+    Atlas(AtlasError),
+    /// Pixels given for encoding don't match image dimensions.
+    PixelDataLength { width: usize, height: usize, expected: usize, actual: usize },
+    /// Pixels given for encoding end before the image does, with the given number of pixels left.
+    MissingPixels(u64),
+    /// Pixels given for encoding continue after the image ends.
+    ExtraPixels { count: u64, remaining: u64 },
+    /// Input is neither a file nor a directory.
+    InputNotFound(PathBuf),
+    /// Input must be a directory, but isn't.
+    InputNotDirectory(PathBuf),
+    /// Output is needed for a directory input, but wasn't given.
+    OutputRequired,
+    /// Output for a directory input already exists as something else.
+    OutputNotDirectory(PathBuf),
+    /// Output directory is inside of the input directory.
+    OutputInsideInput,
+    /// Input file is neither DATA nor PNG.
+    UnrecognizedFormat(PathBuf),
+    /// Input and output are the same file.
+    SameFile(PathBuf),
+    /// Output file exists, while it mustn't be overwritten.
+    OutputExists(PathBuf),
+    /// Output file would be written by multiple input files.
+    OutputCollision { output: PathBuf, inputs: usize },
+    /// Output file would overwrite another input file.
+    OutputOverwritesInput(PathBuf),
+    /// Path doesn't end with a file name.
+    NoFileName(PathBuf),
+    /// Glob pattern can't be parsed.
+    InvalidPattern { pattern: String, message: String },
+    /// Input file can't be opened.
+    OpenInput { path: PathBuf, source: io::Error },
+    /// Output file can't be created.
+    CreateOutput { path: PathBuf, source: io::Error },
+    /// Output directory can't be created.
+    CreateDirectory { path: PathBuf, source: io::Error },
+    /// Directory being scanned can't be read.
+    ReadDirectory { path: PathBuf, source: io::Error },
+    /// Some files of a directory failed to convert.
+    FilesFailed { failed: usize, total: usize },
+    /// DATA file has problems, which are logged by the verification.
+    VerificationFailed(PathBuf),
+    /// Some DATA files of a directory have problems.
+    FilesFailedVerification { failed: usize, total: usize },
+    /// Conversion of the input file failed.
+    Convert { input: PathBuf, source: Box<Error> },
+    /// Thread pool for converting files can't be built.
+    ThreadPool(rayon::ThreadPoolBuildError),
+    /// Reading or writing failed, without a more specific context.
+    Io(io::Error),
+}
+
+impl Error {
+    /// Finds the error behind the input files it happened for.
+    pub fn innermost(&self) -> &Error {
+        match self {
+            Error::Convert { source, .. } => source.innermost(),
+            e => e,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Data(e) => write!(f, "{e}"),
+            Error::Png(e) => write!(f, "{e}"),
+            Error::Atlas(e) => write!(f, "{e}"),
+            Error::PixelDataLength { width, height, expected, actual } => {
+                write!(f, "Image of {width}x{height} must have {expected} bytes of pixel data, but has {actual}")
+            }
+            Error::MissingPixels(count) => write!(f, "Image is missing {count} pixels"),
+            Error::ExtraPixels { count, remaining } => {
+                write!(f, "Image can't have {count} more pixels, only {remaining} are left")
+            }
+            Error::InputNotFound(path) => {
+                write!(f, "Input path can't be recognized as either file or directory: {}", path.display())
+            }
+            Error::InputNotDirectory(path) => write!(f, "Input path must be a directory: {}", path.display()),
+            Error::OutputRequired => write!(f, "Output path must be specified"),
+            Error::OutputNotDirectory(path) => {
+                write!(f, "Output path exists, but isn't a directory: {}", path.display())
+            }
+            Error::OutputInsideInput => write!(f, "Output directory can't be inside of the input directory"),
+            Error::UnrecognizedFormat(path) => {
+                write!(f, "Input file can't be recognized as either DATA or PNG: {}", path.display())
+            }
+            Error::SameFile(path) => write!(f, "Input and output paths point to the same file: {}", path.display()),
+            Error::OutputExists(path) => write!(f, "Output file already exists: {}", path.display()),
+            Error::OutputCollision { output, inputs } => {
+                write!(f, "Output file {} would be written by {} input files", output.display(), inputs)
+            }
+            Error::OutputOverwritesInput(path) => {
+                write!(f, "Output file {} would overwrite another input file", path.display())
+            }
+            Error::NoFileName(path) => write!(f, "Path doesn't have a file name: {}", path.display()),
+            Error::InvalidPattern { pattern, message } => write!(f, "Invalid glob pattern '{pattern}': {message}"),
+            Error::OpenInput { path, source } => write!(f, "Failed to open input file {}: {}", path.display(), source),
+            Error::CreateOutput { path, source } => {
+                write!(f, "Failed to create output file {}: {}", path.display(), source)
+            }
+            Error::CreateDirectory { path, source } => {
+                write!(f, "Failed to create output directory {}: {}", path.display(), source)
+            }
+            Error::ReadDirectory { path, source } => write!(f, "Failed to read directory {}: {}", path.display(), source),
+            Error::FilesFailed { failed, total } => write!(f, "Failed to convert {failed} of {total} files"),
+            Error::VerificationFailed(path) => write!(f, "DATA file failed verification: {}", path.display()),
+            Error::FilesFailedVerification { failed, total } => {
+                write!(f, "{failed} of {total} files failed verification")
+            }
+            Error::Convert { input, source } => write!(f, "{}: {}", input.display(), source),
+            Error::ThreadPool(e) => write!(f, "{e}"),
+            Error::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Data(e) => Some(e),
+            Error::Png(e) => Some(e),
+            Error::Atlas(e) => Some(e),
+            Error::OpenInput { source, .. }
+            | Error::CreateOutput { source, .. }
+            | Error::CreateDirectory { source, .. }
+            | Error::ReadDirectory { source, .. } => Some(source),
+            Error::Convert { source, .. } => Some(source.as_ref()),
+            Error::ThreadPool(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DataError> for Error {
+    fn from(value: DataError) -> Self {
+        Error::Data(value)
+    }
+}
+
+impl From<PngError> for Error {
+    fn from(value: PngError) -> Self {
+        Error::Png(value)
+    }
+}
+
+impl From<AtlasError> for Error {
+    fn from(value: AtlasError) -> Self {
+        Error::Atlas(value)
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(value: png::DecodingError) -> Self {
+        Error::Png(PngError::Decoding(value))
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(value: png::EncodingError) -> Self {
+        Error::Png(PngError::Encoding(value))
+    }
+}
+
+impl From<rayon::ThreadPoolBuildError> for Error {
+    fn from(value: rayon::ThreadPoolBuildError) -> Self {
+        Error::ThreadPool(value)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Io(value)
+    }
+}
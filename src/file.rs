@@ -1,19 +1,254 @@
-use crate::{convert, log};
-use anyhow::{bail, Result};
+use crate::atlas::PackOptions;
+use crate::convert::{DataToPngOptions, Format, PngToDataOptions};
+use crate::data::DataLimits;
+use crate::manifest::{file_stamp, hash_file, Manifest, ManifestEntry};
+use crate::progress::Observer;
+use crate::{atlas, convert, log, verify};
+use crate::error::{Error, Result};
+use glob::{MatchOptions, Pattern};
 use pathdiff::diff_paths;
 use same_file::is_same_file;
-use std::fs::{create_dir_all, read_dir, File};
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt::{self, Debug};
+use std::fs::{create_dir_all, metadata, read_dir, remove_file, rename, File};
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use rayon::prelude::*;
+use rayon::ThreadPool;
 
-pub fn data_to_png(input: PathBuf, output: Option<PathBuf>) -> Result<()> {
-    convert(&input, output.as_ref(), "data", "png", convert::data_to_png)
+/// Options for converting files and directories.
+#[derive(Clone, Default)]
+pub struct FileOptions {
+    /// Convert every file in a directory, even those that haven't changed since the last conversion.
+    pub force: bool,
+    /// What to do when an output file already exists.
+    pub overwrite: OverwritePolicy,
+    /// Which files to convert when the input is a directory.
+    pub scan: ScanOptions,
+    /// Receives events about files being converted.
+    pub observer: Option<Arc<dyn Observer>>,
+    /// Thread pool converting files, instead of the global one.
+    pub thread_pool: Option<Arc<ThreadPool>>,
 }
 
-pub fn png_to_data(input: PathBuf, output: Option<PathBuf>) -> Result<()> {
-    convert(&input, output.as_ref(), "png", "data", convert::png_to_data)
+impl FileOptions {
+    fn notify<F: FnOnce(&dyn Observer)>(&self, f: F) {
+        if let Some(observer) = &self.observer {
+            f(observer.as_ref());
+        }
+    }
+
+    /// Runs the function in the thread pool, if there's one.
+    pub(crate) fn install<T: Send, F: FnOnce() -> T + Send>(&self, f: F) -> T {
+        match &self.thread_pool {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+}
+
+impl Debug for FileOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileOptions")
+            .field("force", &self.force)
+            .field("overwrite", &self.overwrite)
+            .field("scan", &self.scan)
+            .field("observer", &self.observer.as_ref().map(|_| "Observer"))
+            .field("thread_pool", &self.thread_pool)
+            .finish()
+    }
+}
+
+/// Options for finding files in a directory tree.
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    /// Glob patterns of files to include, relative to the directory. Every file is included if empty.
+    pub include: Vec<String>,
+    /// Glob patterns of files and directories to leave out, relative to the directory.
+    pub exclude: Vec<String>,
+    /// How many levels of subdirectories to look into, unlimited if not set.
+    pub max_depth: Option<usize>,
+    /// Include files and directories with names starting with a dot.
+    pub hidden: bool,
+    /// Follow symbolic links, which are skipped otherwise.
+    pub follow_symlinks: bool,
+}
+
+/// Policy for output files that already exist.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Leave the existing file alone, without converting the input.
+    SkipExisting,
+    /// Replace the existing file, keeping it as a backup with `.bak` suffix.
+    Backup,
+    /// Fail the conversion of the input.
+    FailIfExists,
+}
+
+pub fn data_to_png(
+    input: PathBuf,
+    output: Option<PathBuf>,
+    options: &DataToPngOptions,
+    file_options: &FileOptions,
+) -> Result<()> {
+    let fingerprint = options.fingerprint();
+    convert_with_fingerprint(&input, output.as_ref(), "data", "png", file_options, fingerprint, |i, o| {
+        convert::data_to_png_with_options(i, o, options)
+    })
+}
+
+pub fn png_to_data(
+    input: PathBuf,
+    output: Option<PathBuf>,
+    options: &PngToDataOptions,
+    file_options: &FileOptions,
+) -> Result<()> {
+    let fingerprint = options.fingerprint();
+    convert_with_fingerprint(&input, output.as_ref(), "png", "data", file_options, fingerprint, |i, o| {
+        convert::png_to_data_with_options(i, o, options)
+    })
+}
+
+pub fn slice_atlas(input: PathBuf, output: Option<PathBuf>) -> Result<()> {
+    match output {
+        None => Err(Error::OutputRequired),
+        Some(o) => atlas::slice(&input, &o),
+    }
+}
+
+pub fn pack_atlas(input: PathBuf, output: Option<PathBuf>, options: &PackOptions) -> Result<()> {
+    match output {
+        None => Err(Error::OutputRequired),
+        Some(o) => atlas::pack(&input, &o, options),
+    }
+}
+
+/// Converts files into DATA or PNG, depending on what format each of them is detected to have.
+pub fn convert_auto(
+    input: PathBuf,
+    output: Option<PathBuf>,
+    data_options: &DataToPngOptions,
+    png_options: &PngToDataOptions,
+    file_options: &FileOptions,
+) -> Result<()> {
+    if input.is_file() {
+        let format = detect_file_format(&input)?;
+        let convert_fn = |i: &mut BufReader<File>, o: &mut BufWriter<File>| {
+            convert::convert_from(format, i, o, data_options, png_options)
+        };
+        let output = single_file_output_path(&input, output.as_ref(), format.target().extension())?;
+        convert_single_file(&input, &output, file_options, convert_fn)
+    } else if input.is_dir() {
+        log!(debug: "Input path is a directory: {}", input.display());
+
+        let output = check_output_dir(output.as_ref())?;
+        // Outputs of both formats would get scanned and converted back otherwise
+        check_output_outside_input(&input, output)?;
+
+        let mut items: Vec<PathBuf> = Vec::new();
+        scan_dir(&input, Format::Data.extension(), &file_options.scan, &mut items)?;
+        scan_dir(&input, Format::Png.extension(), &file_options.scan, &mut items)?;
+
+        let plan_fn = |item: &PathBuf| {
+            let format = detect_file_format(item)?;
+            let item_output_path = mirrored_output_path(&input, output, item).with_extension(format.target().extension());
+            Ok((item_output_path, options_fingerprint(format, data_options, png_options)))
+        };
+        convert_items(&input, output, &items, file_options, true, plan_fn, |item_input_path, item_output_path| {
+            convert_file_auto(item_input_path, item_output_path, data_options, png_options, file_options)
+                .map(|(_, _, outcome)| outcome)
+        })
+    } else {
+        Err(Error::InputNotFound(input.clone()))
+    }
+}
+
+/// Converts a single file into DATA or PNG, depending on its detected format.
+/// Extension of the output path gets replaced with the one of the target format,
+/// the final path is returned along with the detected format and whether the file was converted.
+pub(crate) fn convert_file_auto(
+    input: &PathBuf,
+    output: &Path,
+    data_options: &DataToPngOptions,
+    png_options: &PngToDataOptions,
+    file_options: &FileOptions,
+) -> Result<(PathBuf, Format, FileOutcome)> {
+    // Output extension is only known once the input format is detected
+    let format = detect_file_format(input)?;
+    let output = output.with_extension(format.target().extension());
+    let outcome = convert_file_to_file(input, &output, file_options, |i, o| {
+        convert::convert_from(format, i, o, data_options, png_options)
+    })?;
+    Ok((output, format, outcome))
+}
+
+/// Fingerprint of the options used to convert from the given format.
+pub(crate) fn options_fingerprint(format: Format, data_options: &DataToPngOptions, png_options: &PngToDataOptions) -> u64 {
+    match format {
+        Format::Data => data_options.fingerprint(),
+        Format::Png => png_options.fingerprint(),
+    }
+}
+
+/// Checks DATA files for problems without converting them, logging every issue found.
+pub fn verify_data(input: PathBuf, limits: &DataLimits) -> Result<()> {
+    if input.is_file() {
+        if !verify_file(&input, limits)? {
+            return Err(Error::VerificationFailed(input));
+        }
+        Ok(())
+    } else if input.is_dir() {
+        log!(debug: "Input path is a directory: {}", input.display());
+
+        let mut items: Vec<PathBuf> = Vec::new();
+        scan_dir(&input, Format::Data.extension(), &ScanOptions::default(), &mut items)?;
+
+        log!("Found {} input files", items.len());
+        let valid = AtomicUsize::new(0);
+        items.par_iter().for_each(|item| match verify_file(item, limits) {
+            Ok(true) => { valid.fetch_add(1, Ordering::Relaxed); }
+            Ok(false) => (),
+            Err(e) => log!(error: "Error verifying: {}", e),
+        });
+
+        let valid = valid.into_inner();
+        if !items.is_empty() {
+            log!("{}/{} verified successfully", valid, items.len());
+        }
+
+        if valid < items.len() {
+            return Err(Error::FilesFailedVerification { failed: items.len() - valid, total: items.len() });
+        }
+
+        Ok(())
+    } else {
+        Err(Error::InputNotFound(input.clone()))
+    }
+}
+
+fn verify_file(input: &PathBuf, limits: &DataLimits) -> Result<bool> {
+    let mut input_reader = match File::open(input) {
+        Ok(f) => f,
+        Err(e) => return Err(Error::OpenInput { path: input.clone(), source: e }),
+    };
+
+    let verification = verify::verify_with_limits(&mut input_reader, limits)?;
+    for issue in &verification.issues {
+        log!(warn: "{}: {}", input.display(), issue);
+    }
+
+    if verification.is_valid() {
+        let (width, height, has_alpha) = (verification.width, verification.height, verification.has_alpha);
+        log!("{}: OK, {width}x{height}, has alpha: {has_alpha}", input.display());
+    }
+
+    Ok(verification.is_valid())
 }
 
 pub fn convert<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()> + Sync>(
@@ -22,128 +257,534 @@ pub fn convert<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()> +
     input_ext: &str,
     output_ext: &str,
     convert_fn: F,
+) -> Result<()> {
+    convert_with_options(input, output, input_ext, output_ext, &FileOptions::default(), convert_fn)
+}
+
+pub fn convert_with_options<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()> + Sync>(
+    input: &PathBuf,
+    output: Option<&PathBuf>,
+    input_ext: &str,
+    output_ext: &str,
+    options: &FileOptions,
+    convert_fn: F,
+) -> Result<()> {
+    convert_files(input, output, input_ext, output_ext, options, None, convert_fn)
+}
+
+/// Converts files, skipping those unchanged since they were last converted into the output directory.
+/// Fingerprint of the conversion is recorded in the manifest,
+/// so that files converted by a different function or with different options aren't skipped as unchanged.
+pub fn convert_with_fingerprint<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()> + Sync>(
+    input: &PathBuf,
+    output: Option<&PathBuf>,
+    input_ext: &str,
+    output_ext: &str,
+    options: &FileOptions,
+    fingerprint: u64,
+    convert_fn: F,
+) -> Result<()> {
+    convert_files(input, output, input_ext, output_ext, options, Some(fingerprint), convert_fn)
+}
+
+/// Converts files, using the manifest only when there's a fingerprint to record in it.
+fn convert_files<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()> + Sync>(
+    input: &PathBuf,
+    output: Option<&PathBuf>,
+    input_ext: &str,
+    output_ext: &str,
+    options: &FileOptions,
+    fingerprint: Option<u64>,
+    convert_fn: F,
 ) -> Result<()> {
     if input.is_file() {
-        if output.is_none() {
-            let input_dir = input.parent().unwrap().to_path_buf();
-            convert_file_to_dir(input, &input_dir, output_ext, convert_fn)
-        } else {
-            convert_file_to_file(input, output.unwrap(), convert_fn)
-        }
+        let output = single_file_output_path(input, output, output_ext)?;
+        convert_single_file(input, &output, options, convert_fn)
     } else if input.is_dir() {
-        log!("Input path is a directory: {}", input.display());
+        log!(debug: "Input path is a directory: {}", input.display());
 
-        let output = match output {
-            None => bail!("Output path must be specified"),
-            Some(o) => o,
-        };
+        let output = check_output_dir(output)?;
 
-        if output.exists() && !output.is_dir() {
-            bail!("Output path exists, but isn't a directory: {}", output.display());
+        convert_dir_to_dir(input, output, input_ext, output_ext, options, fingerprint, convert_fn)
+    } else {
+        Err(Error::InputNotFound(input.clone()))
+    }
+}
+
+fn check_output_dir(output: Option<&PathBuf>) -> Result<&PathBuf> {
+    let output = match output {
+        None => return Err(Error::OutputRequired),
+        Some(o) => o,
+    };
+
+    if output.exists() && !output.is_dir() {
+        return Err(Error::OutputNotDirectory(output.clone()));
+    }
+
+    Ok(output)
+}
+
+/// Fails if the output directory is the input directory or inside of it.
+fn check_output_outside_input(input: &Path, output: &Path) -> Result<()> {
+    // Output directory may not exist yet, so its closest existing ancestor is resolved instead
+    let mut existing = output;
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => break,
         }
+    }
+    let existing = if existing.as_os_str().is_empty() { Path::new(".") } else { existing };
 
-        convert_dir_to_dir(input, output, input_ext, output_ext, convert_fn)
-    } else {
-        bail!("Input path can't be recognized as either file or directory: {}", input.display());
+    let mut output = existing.canonicalize()?;
+    output.extend(missing.iter().rev());
+    if output.starts_with(input.canonicalize()?) {
+        return Err(Error::OutputInsideInput);
     }
+    Ok(())
+}
+
+fn detect_file_format(input: &PathBuf) -> Result<Format> {
+    let mut header = Vec::new();
+    let len = match File::open(input) {
+        Ok(f) => {
+            let len = f.metadata()?.len();
+            f.take(16).read_to_end(&mut header)?;
+            len
+        }
+        Err(e) => return Err(Error::OpenInput { path: input.clone(), source: e }),
+    };
+
+    match convert::detect_format(&header, len) {
+        Some(format) => Ok(format),
+        None => Err(Error::UnrecognizedFormat(input.clone())),
+    }
+}
+
+/// Outcome of converting a single file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FileOutcome {
+    Converted,
+    /// Output already existed and was kept, as requested by [`OverwritePolicy::SkipExisting`].
+    SkippedExisting,
 }
 
 fn convert_file_to_file<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()>>(
     input: &PathBuf,
     output: &PathBuf,
+    options: &FileOptions,
     convert_fn: F,
-) -> Result<()> {
-    log!("Input file: {}", input.display());
-    log!("Output file: {}", output.display());
+) -> Result<FileOutcome> {
+    log!(debug: "Input file: {}", input.display());
+    log!(debug: "Output file: {}", output.display());
 
     if output.exists() && is_same_file(&input, &output)? {
-        bail!("Input and output paths point to the same file");
+        return Err(Error::SameFile(output.clone()));
     }
 
-    let output_dir = output.parent().unwrap();
+    let output_dir = output.parent().unwrap_or(Path::new(""));
     if !output_dir.exists() {
-        log!("Ensuring output directory exists {}", output_dir.display());
+        log!(debug: "Ensuring output directory exists {}", output_dir.display());
         match create_dir_all(output_dir) {
             Ok(_) => (),
-            Err(e) => bail!("Failed to create output directory {}: {}", output_dir.display(), e),
+            Err(e) => return Err(Error::CreateDirectory { path: output_dir.to_path_buf(), source: e }),
         }
     }
 
     let mut input_reader = match File::open(&input) {
         Ok(f) => BufReader::new(f),
-        Err(e) => bail!("Failed to open input file {}: {}", input.display(), e),
+        Err(e) => return Err(Error::OpenInput { path: input.clone(), source: e }),
     };
 
-    let mut output_writer = match File::create(&output) {
+    if output.exists() {
+        match options.overwrite {
+            OverwritePolicy::Overwrite | OverwritePolicy::Backup => (),
+            OverwritePolicy::SkipExisting => {
+                log!("Skipping existing output file {}", output.display());
+                return Ok(FileOutcome::SkippedExisting);
+            }
+            OverwritePolicy::FailIfExists => return Err(Error::OutputExists(output.clone())),
+        }
+    }
+
+    let backup = options.overwrite == OverwritePolicy::Backup;
+    write_atomically(output, backup, |output_writer| convert_fn(&mut input_reader, output_writer))?;
+    Ok(FileOutcome::Converted)
+}
+
+/// Writes the file through a temporary sibling, which only replaces the target once completely written.
+/// Existing target can be kept as a backup with `.bak` suffix.
+pub(crate) fn write_atomically<F: FnOnce(&mut BufWriter<File>) -> Result<()>>(
+    path: &Path,
+    backup: bool,
+    write_fn: F,
+) -> Result<()> {
+    static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let Some(file_name) = path.file_name() else {
+        return Err(Error::NoFileName(path.to_path_buf()));
+    };
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}-{}.tmp", std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut writer = match File::create(&temp_path) {
         Ok(f) => BufWriter::new(f),
-        Err(e) => bail!("Failed to create output file {}: {}", output.display(), e),
+        Err(e) => return Err(Error::CreateOutput { path: path.to_path_buf(), source: e }),
     };
 
-    convert_fn(&mut input_reader, &mut output_writer)?;
+    let result = write_fn(&mut writer)
+        .and_then(|_| writer.into_inner().map_err(|e| e.into_error().into()))
+        .and_then(|file| Ok(file.sync_all()?))
+        .and_then(|_| {
+            if backup && path.exists() {
+                let mut backup_name = file_name.to_os_string();
+                backup_name.push(".bak");
+                rename(path, path.with_file_name(backup_name))?;
+            }
+            Ok(rename(&temp_path, path)?)
+        });
 
-    Ok(())
+    // Partially written output is never left behind
+    if result.is_err() {
+        let _ = remove_file(&temp_path);
+    }
+    result
+}
+
+/// Finds output path of a single input file, which goes next to the input unless specified otherwise.
+/// When the specified output is a directory, the file goes there.
+fn single_file_output_path(input: &Path, output: Option<&PathBuf>, output_ext: &str) -> Result<PathBuf> {
+    let output_dir = match output {
+        None => input.parent().unwrap_or(Path::new("")),
+        Some(o) if o.is_dir() => o,
+        Some(o) => return Ok(o.clone()),
+    };
+
+    let Some(file_name) = input.file_name() else {
+        return Err(Error::NoFileName(input.to_path_buf()));
+    };
+    // Only the last extension is replaced, so that inner dots of the name are kept
+    Ok(output_dir.join(file_name).with_extension(output_ext))
 }
 
-fn convert_file_to_dir<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()>>(
+/// Converts a single file, reported to the observer as a conversion of just one file.
+fn convert_single_file<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()> + Sync>(
     input: &PathBuf,
     output: &PathBuf,
-    output_ext: &str,
+    options: &FileOptions,
     convert_fn: F,
 ) -> Result<()> {
-    let file_name = input.file_stem().unwrap().to_str().unwrap();
-    let output_file_path = output.join(file_name).with_extension(output_ext);
+    let bytes = metadata(input).map_or(0, |m| m.len());
+    options.notify(|o| {
+        o.started(1, bytes);
+        o.file_started(input);
+    });
+
+    let result = options.install(|| convert_file_to_file(input, output, options, &convert_fn));
 
-    convert_file_to_file(input, &output_file_path, convert_fn)
+    options.notify(|o| {
+        match &result {
+            Ok(outcome) => o.file_finished(input, output, bytes, *outcome == FileOutcome::SkippedExisting),
+            Err(e) => o.file_failed(input, bytes, e),
+        }
+        o.finished();
+    });
+    result.map(|_| ())
 }
 
 fn convert_dir_to_dir<F: Fn(&mut BufReader<File>, &mut BufWriter<File>) -> Result<()> + Sync>(
-    input: &PathBuf,
-    output: &PathBuf,
+    input: &Path,
+    output: &Path,
     input_ext: &str,
     output_ext: &str,
+    options: &FileOptions,
+    fingerprint: Option<u64>,
     convert_fn: F,
 ) -> Result<()> {
     let mut items: Vec<PathBuf> = Vec::new();
-    scan_dir(&input, input_ext, 0, &mut items)?;
+    scan_dir(input, input_ext, &options.scan, &mut items)?;
+
+    let use_manifest = fingerprint.is_some();
+    let fingerprint = fingerprint.unwrap_or_default();
+    let plan_fn = |item: &PathBuf| Ok((mirrored_output_path(input, output, item).with_extension(output_ext), fingerprint));
+    convert_items(input, output, &items, options, use_manifest, plan_fn, |item_input_path, item_output_path| {
+        convert_file_to_file(item_input_path, item_output_path, options, &convert_fn)
+    })
+}
 
-    log!("Found {} input files", items.len());
-    let success = AtomicUsize::new(0);
-    items.par_iter().for_each(|item_input_path| {
-        let file_name = item_input_path.file_stem().unwrap().to_str().unwrap();
-        let relative_file_path = diff_paths(item_input_path, &input).unwrap();
-        let relative_dir_path = relative_file_path.parent().unwrap();
-        let item_output_path = output.join(relative_dir_path).join(file_name).with_extension(output_ext);
+/// Outcome of converting a single item in a directory.
+enum ItemResult {
+    Converted(ManifestEntry),
+    Skipped(ManifestEntry),
+    /// Output already existed, so there's nothing converted to remember in the manifest.
+    SkippedExisting,
+    Failed,
+}
+
+/// Converts every item in parallel, mirroring directory structure of the input into the output.
+/// Output path of every item is found first, along with fingerprint of the options it gets converted with,
+/// so that items colliding on the same output all fail without writing it,
+/// as do items whose output is an input of another item.
+///
+/// When using the manifest, items that haven't changed since they were last converted are skipped,
+/// unless forced otherwise.
+fn convert_items<P, F>(
+    input: &Path,
+    output: &Path,
+    items: &[PathBuf],
+    options: &FileOptions,
+    use_manifest: bool,
+    plan_fn: P,
+    convert_fn: F,
+) -> Result<()>
+where
+    P: Fn(&PathBuf) -> Result<(PathBuf, u64)> + Sync,
+    F: Fn(&PathBuf, &PathBuf) -> Result<FileOutcome> + Sync,
+{
+    options.install(|| {
+        log!("Found {} input files", items.len());
+        let manifest = if options.force || !use_manifest { Manifest::default() } else { Manifest::load(output) };
 
-        match convert_file_to_file(item_input_path, &item_output_path, &convert_fn) {
-            Ok(_) => { success.fetch_add(1, Ordering::Relaxed); }
-            Err(e) => log!("Error converting: {}", e),
+        let sizes: Vec<u64> = items.par_iter().map(|item| metadata(item).map_or(0, |m| m.len())).collect();
+        options.notify(|o| o.started(items.len(), sizes.iter().sum()));
+
+        let plans: Vec<Result<(PathBuf, u64)>> = items.par_iter().map(&plan_fn).collect();
+        let mut output_counts: HashMap<PathBuf, usize> = HashMap::new();
+        for (item_output_path, _) in plans.iter().flatten() {
+            *output_counts.entry(item_output_path.clone()).or_default() += 1;
         }
-    });
+        // Inputs are resolved, so that they're recognized however the output directory is spelled
+        let input_paths: HashSet<PathBuf> = items.par_iter().filter_map(|item| item.canonicalize().ok()).collect();
+
+        let results: Vec<(PathBuf, ItemResult)> = items.par_iter().zip(plans).zip(&sizes).map(|((item_input_path, plan), &size)| {
+            let relative_file_path = diff_paths(item_input_path, input).unwrap_or_else(|| item_input_path.clone());
+            options.notify(|o| o.file_started(item_input_path));
 
-    if items.len() > 0 {
-        log!("{}/{} converted successfully", success.into_inner(), items.len());
+            let result = plan.and_then(|(item_output_path, fingerprint)| match output_counts[&item_output_path] {
+                1 if overwrites_input(item_input_path, &item_output_path, &input_paths) => {
+                    Err(Error::OutputOverwritesInput(item_output_path))
+                }
+                1 => {
+                    let previous = manifest.get(&relative_file_path);
+                    convert_item(item_input_path, &item_output_path, output, fingerprint, previous, &convert_fn)
+                        .map(|result| (result, item_output_path))
+                }
+                inputs => Err(Error::OutputCollision { output: item_output_path, inputs }),
+            });
+            let result = match result {
+                Ok((result, item_output_path)) => {
+                    let skipped = matches!(result, ItemResult::Skipped(_) | ItemResult::SkippedExisting);
+                    options.notify(|o| o.file_finished(item_input_path, &item_output_path, size, skipped));
+                    result
+                }
+                Err(e) => {
+                    let e = Error::Convert { input: item_input_path.clone(), source: Box::new(e) };
+                    log!(error: "Error converting: {}", e);
+                    options.notify(|o| o.file_failed(item_input_path, size, &e));
+                    ItemResult::Failed
+                }
+            };
+            (relative_file_path, result)
+        }).collect();
+        options.notify(|o| o.finished());
+
+        // Failed items are left out of the manifest, so that they are retried next time
+        let mut new_manifest = Manifest::default();
+        let (mut converted, mut skipped, mut existing, mut failed) = (0, 0, 0, 0);
+        for (relative_file_path, result) in results {
+            match result {
+                ItemResult::Converted(entry) => {
+                    converted += 1;
+                    new_manifest.insert(relative_file_path, entry);
+                }
+                ItemResult::Skipped(entry) => {
+                    skipped += 1;
+                    new_manifest.insert(relative_file_path, entry);
+                }
+                ItemResult::SkippedExisting => existing += 1,
+                ItemResult::Failed => failed += 1,
+            }
+        }
+
+        if !items.is_empty() {
+            if existing > 0 {
+                log!(
+                    "{} converted, {} skipped as unchanged, {} skipped as existing, {} failed",
+                    converted, skipped, existing, failed
+                );
+            } else {
+                log!("{} converted, {} skipped as unchanged, {} failed", converted, skipped, failed);
+            }
+            if use_manifest && let Err(e) = new_manifest.save(output) {
+                log!(warn: "Failed to save manifest into {}: {}", output.display(), e);
+            }
+        }
+
+        if failed > 0 {
+            return Err(Error::FilesFailed { failed, total: items.len() });
+        }
+
+        Ok(())
+    })
+}
+
+/// Checks whether the output of the item is an input of another item, which would be lost when written.
+fn overwrites_input(input: &Path, output: &Path, input_paths: &HashSet<PathBuf>) -> bool {
+    match output.canonicalize() {
+        Ok(output) => input_paths.contains(&output) && input.canonicalize().is_ok_and(|input| input != output),
+        Err(_) => false,
     }
+}
 
-    Ok(())
+/// Finds where the input item goes in the output directory, still with the extension of the input.
+pub(crate) fn mirrored_output_path(input: &Path, output: &Path, item: &Path) -> PathBuf {
+    match item.strip_prefix(input) {
+        Ok(relative_file_path) => output.join(relative_file_path),
+        Err(_) => output.join(item.file_name().unwrap_or(item.as_os_str())),
+    }
 }
 
-fn scan_dir(path: &PathBuf, ext: &str, depth: u8, result: &mut Vec<PathBuf>) -> Result<()> {
-    const MAX_DEPTH: u8 = 16;
-    if depth > MAX_DEPTH {
-        return Ok(());
+fn convert_item<F: Fn(&PathBuf, &PathBuf) -> Result<FileOutcome>>(
+    input: &PathBuf,
+    output: &PathBuf,
+    output_dir: &Path,
+    fingerprint: u64,
+    previous: Option<&ManifestEntry>,
+    convert_fn: F,
+) -> Result<ItemResult> {
+    let (size, modified) = file_stamp(input)?;
+    let relative_output = diff_paths(output, output_dir).unwrap_or_else(|| output.clone());
+
+    // Output converted with different options is outdated, even when the input hasn't changed
+    let previous = previous
+        .filter(|p| p.size == size && p.options == fingerprint && p.output == relative_output && output.is_file());
+    if let Some(previous) = previous {
+        // Comparing contents is only needed when the file was touched
+        if previous.modified == modified {
+            return Ok(ItemResult::Skipped(previous.clone()));
+        }
+        if hash_file(input)? == previous.hash {
+            return Ok(ItemResult::Skipped(ManifestEntry { modified, ..previous.clone() }));
+        }
+    }
+
+    if convert_fn(input, output)? == FileOutcome::SkippedExisting {
+        return Ok(ItemResult::SkippedExisting);
     }
+    let entry = ManifestEntry { hash: hash_file(input)?, size, modified, options: fingerprint, output: relative_output };
+    Ok(ItemResult::Converted(entry))
+}
+
+/// Finds files with the given extension in the directory tree, according to the options.
+pub(crate) fn scan_dir(root: &Path, ext: &str, options: &ScanOptions, result: &mut Vec<PathBuf>) -> Result<()> {
+    let scanner = Scanner {
+        root,
+        ext,
+        options,
+        include: compile_patterns(&options.include)?,
+        exclude: compile_patterns(&options.exclude)?,
+    };
+
+    let mut ancestors = Vec::new();
+    if options.follow_symlinks {
+        ancestors.push(root.canonicalize()?);
+    }
+    scanner.scan(root, 0, &mut ancestors, result)
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|p| Pattern::new(p).map_err(|e| Error::InvalidPattern { pattern: p.clone(), message: e.to_string() }))
+        .collect()
+}
 
-    for entry in read_dir(path)? {
-        let entry = entry?;
-        let child_path = entry.path();
-        let child_file_type = entry.file_type()?;
+/// Patterns are matched against paths relative to the scanned directory, where `*` never crosses a `/`.
+const MATCH_OPTIONS: MatchOptions =
+    MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
 
-        if child_file_type.is_dir() {
-            scan_dir(&child_path, ext, depth + 1, result)?;
-        } else if child_path.extension().unwrap().eq_ignore_ascii_case(ext) {
-            result.push(child_path);
+struct Scanner<'a> {
+    root: &'a Path,
+    ext: &'a str,
+    options: &'a ScanOptions,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Scanner<'_> {
+    /// Scans the directory, where ancestors are canonical paths of directories leading to it,
+    /// only tracked when following symlinks.
+    fn scan(&self, dir: &Path, depth: usize, ancestors: &mut Vec<PathBuf>, result: &mut Vec<PathBuf>) -> Result<()> {
+        let entries = match read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => return Err(Error::ReadDirectory { path: dir.to_path_buf(), source: e }),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if !self.options.hidden && entry.file_name().as_encoded_bytes().starts_with(b".") {
+                continue;
+            }
+
+            let mut file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                if !self.options.follow_symlinks {
+                    continue;
+                }
+                match metadata(&path) {
+                    Ok(m) => file_type = m.file_type(),
+                    Err(e) => {
+                        log!(warn: "Skipping broken link {}: {}", path.display(), e);
+                        continue;
+                    }
+                }
+            }
+
+            let relative = self.relative_path(&path);
+            if self.exclude.iter().any(|p| p.matches_with(&relative, MATCH_OPTIONS)) {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if self.options.max_depth.is_some_and(|max| depth >= max) {
+                    log!(warn: "Skipping directory beyond the depth limit: {}", path.display());
+                    continue;
+                }
+                if !self.options.follow_symlinks {
+                    self.scan(&path, depth + 1, ancestors, result)?;
+                    continue;
+                }
+
+                // Links pointing back up the tree would be scanned forever
+                let canonical = path.canonicalize()?;
+                if ancestors.contains(&canonical) {
+                    log!(warn: "Skipping link cycle: {}", path.display());
+                    continue;
+                }
+                ancestors.push(canonical);
+                self.scan(&path, depth + 1, ancestors, result)?;
+                ancestors.pop();
+            } else if file_type.is_file()
+                && path.extension().is_some_and(|e| e.eq_ignore_ascii_case(self.ext))
+                && (self.include.is_empty() || self.include.iter().any(|p| p.matches_with(&relative, MATCH_OPTIONS)))
+            {
+                result.push(path);
+            }
         }
+
+        Ok(())
     }
 
-    Ok(())
+    /// Path relative to the scanned directory, always separated with `/` to match the same patterns everywhere.
+    fn relative_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(self.root).unwrap_or(path);
+        let components: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+        components.join("/")
+    }
 }
@@ -1,7 +1,17 @@
+pub mod alpha;
+pub mod atlas;
 pub mod convert;
+pub mod data;
+pub mod dither;
+pub mod error;
 pub mod file;
 pub mod log;
+pub mod manifest;
 pub mod rayon;
 pub mod math;
 pub mod unpack;
 pub mod png;
+pub mod progress;
+pub mod report;
+pub mod verify;
+pub mod watch;
@@ -1,11 +1,134 @@
+use std::fmt::{self, Arguments, Display, Write as _};
+use std::io::{stderr, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+
+/// Logs a message, optionally prefixed with its level, which is `info` if not given.
+///
+/// Messages are dropped unless a logger is set with [`set_logger`].
 #[macro_export]
 macro_rules! log {
-    ($($arg:tt)*) => (
-        {
-            let message = format!($($arg)*);
-            let thread_name = std::thread::current().name().unwrap_or_default().to_string();
-            let extended_message = format!("[{}] {}", thread_name, message);
-            println!("{}", extended_message);
+    (error: $($arg:tt)*) => ($crate::log::write($crate::log::Level::Error, format_args!($($arg)*)));
+    (warn: $($arg:tt)*) => ($crate::log::write($crate::log::Level::Warn, format_args!($($arg)*)));
+    (info: $($arg:tt)*) => ($crate::log::write($crate::log::Level::Info, format_args!($($arg)*)));
+    (debug: $($arg:tt)*) => ($crate::log::write($crate::log::Level::Debug, format_args!($($arg)*)));
+    (trace: $($arg:tt)*) => ($crate::log::write($crate::log::Level::Trace, format_args!($($arg)*)));
+    ($($arg:tt)*) => ($crate::log::write($crate::log::Level::Info, format_args!($($arg)*)));
+}
+
+/// Importance of a logged message, from the most important one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Single logged message.
+#[derive(Clone, Copy, Debug)]
+pub struct Record<'a> {
+    pub level: Level,
+    /// Name of the thread logging the message, empty for unnamed threads.
+    pub thread: &'a str,
+    pub message: &'a str,
+}
+
+type Logger = Box<dyn Fn(&Record) + Send + Sync>;
+
+static LOGGER: RwLock<Option<Logger>> = RwLock::new(None);
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Sets the function receiving every message that passes the maximum level, replacing the previous one.
+pub fn set_logger<F: Fn(&Record) + Send + Sync + 'static>(logger: F) {
+    *LOGGER.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(logger));
+}
+
+/// Removes the logger, after which messages are dropped.
+pub fn clear_logger() {
+    *LOGGER.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Sets the least important level of messages still logged, or turns logging off if not given.
+pub fn set_max_level(level: Option<Level>) {
+    MAX_LEVEL.store(level.map_or(0, |l| l as u8), Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Passes the message to the logger, used by the [`log!`](crate::log!) macro.
+pub fn write(level: Level, args: Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let logger = LOGGER.read().unwrap_or_else(|e| e.into_inner());
+    let Some(logger) = logger.as_ref() else { return };
+
+    let message = args.to_string();
+    let thread = std::thread::current();
+    logger(&Record { level, thread: thread.name().unwrap_or_default(), message: &message });
+}
+
+/// Logger writing messages into stderr, either as text or one JSON object per line.
+pub fn stderr_logger(json: bool) -> impl Fn(&Record) + Send + Sync {
+    move |record| {
+        let line = if json { format_json(record) } else { format_text(record) };
+        let _ = writeln!(stderr().lock(), "{}", line);
+    }
+}
+
+fn format_text(record: &Record) -> String {
+    match record.level {
+        Level::Info => format!("[{}] {}", record.thread, record.message),
+        level => format!("[{}] {}: {}", record.thread, level.as_str().to_uppercase(), record.message),
+    }
+}
+
+fn format_json(record: &Record) -> String {
+    format!(
+        r#"{{"level":{},"thread":{},"message":{}}}"#,
+        json_string(record.level.as_str()),
+        json_string(record.thread),
+        json_string(record.message)
+    )
+}
+
+/// Quotes the string for JSON, escaping characters where needed.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(result, "\\u{:04x}", c as u32); }
+            c => result.push(c),
         }
-    );
+    }
+    result.push('"');
+    result
 }
@@ -1,36 +1,439 @@
-use anyhow::anyhow;
-use celeste_converter::file::{data_to_png, png_to_data};
+use celeste_converter::alpha::AlphaMode;
+use celeste_converter::atlas::PackOptions;
+use celeste_converter::convert::{DataToPngOptions, PngToDataOptions};
+use celeste_converter::data::DataLimits;
+use celeste_converter::dither::Dither;
+use celeste_converter::file::{
+    convert_auto, data_to_png, pack_atlas, png_to_data, slice_atlas, verify_data, FileOptions,
+    OverwritePolicy, ScanOptions,
+};
 use celeste_converter::log;
-use std::env;
-use std::path::PathBuf;
+use celeste_converter::log::{set_logger, set_max_level, stderr_logger, Level};
+use celeste_converter::progress::{Observer, Observers, ProgressBar};
+use celeste_converter::report::Report;
 use celeste_converter::rayon::init_rayon;
+use celeste_converter::watch::{watch, WatchOptions};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Converts Celeste graphics between DATA and PNG formats.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log more details, repeat for even more
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Format of messages logged into stderr
+    #[arg(long, global = true, value_enum, default_value_t = LogFormatArg::Text)]
+    log_format: LogFormatArg,
+    /// When to show progress of converting files, which by default needs stderr to be a terminal with text logs
+    #[arg(long, global = true, value_enum, default_value_t = ProgressArg::Auto)]
+    progress: ProgressArg,
+    /// Number of threads converting files, one per CPU by default, with 1 doing everything on the main thread
+    #[arg(long, global = true, default_value_t = 0, hide_default_value = true)]
+    threads: usize,
+}
+
+impl Cli {
+    fn progress_bar(&self) -> Option<ProgressBar> {
+        match self.progress {
+            ProgressArg::Auto if self.quiet || self.log_format == LogFormatArg::Json => None,
+            ProgressArg::Auto => ProgressBar::for_terminal(),
+            ProgressArg::Always => Some(ProgressBar::new()),
+            ProgressArg::Never => None,
+        }
+    }
+
+    fn log_level(&self) -> Level {
+        match (self.quiet, self.verbose) {
+            (true, _) => Level::Error,
+            (false, 0) => Level::Info,
+            (false, 1) => Level::Debug,
+            (false, _) => Level::Trace,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormatArg {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ProgressArg {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert between Celeste DATA format and PNG, detecting the direction for each file
+    Convert {
+        /// Input file or directory
+        input: PathBuf,
+        /// Output file or directory, defaults to the input file's directory
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        png: PngArgs,
+        #[command(flatten)]
+        data: DataArgs,
+        #[command(flatten)]
+        conversion: ConversionArgs,
+        #[command(flatten)]
+        file: FileArgs,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// Convert from Celeste DATA format into PNG
+    Data2png {
+        /// Input file or directory
+        input: PathBuf,
+        /// Output file or directory, defaults to the input file's directory
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        data: DataArgs,
+        #[command(flatten)]
+        conversion: ConversionArgs,
+        #[command(flatten)]
+        file: FileArgs,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// Convert from PNG into Celeste DATA format
+    Png2data {
+        /// Input file or directory
+        input: PathBuf,
+        /// Output file or directory, defaults to the input file's directory
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        png: PngArgs,
+        #[command(flatten)]
+        conversion: ConversionArgs,
+        #[command(flatten)]
+        file: FileArgs,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// Keep converting files between Celeste DATA format and PNG as they change
+    Watch {
+        /// Input directory
+        input: PathBuf,
+        /// Output directory
+        output: PathBuf,
+        /// How often to check for changes, in milliseconds
+        #[arg(long, default_value_t = WatchOptions::default().poll_interval.as_millis() as u64)]
+        poll_interval: u64,
+        /// How long files must stay unchanged before being converted, in milliseconds
+        #[arg(long, default_value_t = WatchOptions::default().debounce.as_millis() as u64)]
+        debounce: u64,
+        #[command(flatten)]
+        png: PngArgs,
+        #[command(flatten)]
+        data: DataArgs,
+        #[command(flatten)]
+        conversion: ConversionArgs,
+        #[command(flatten)]
+        file: FileArgs,
+    },
+    /// Check Celeste DATA files for problems without converting them
+    Verify {
+        /// Input file or directory
+        input: PathBuf,
+        #[command(flatten)]
+        data: DataArgs,
+    },
+    /// Slice Celeste atlas (META file and its DATA pages) into PNG sprites
+    Slice {
+        /// Input META file
+        input: PathBuf,
+        /// Output directory
+        output: PathBuf,
+    },
+    /// Pack PNG sprites into Celeste atlas (META file and its DATA pages)
+    Pack {
+        /// Input directory with PNG sprites
+        input: PathBuf,
+        /// Output META file, pages are written next to it
+        output: PathBuf,
+        /// Maximum width and height of a single page
+        #[arg(long, default_value_t = PackOptions::default().max_page_size)]
+        max_page_size: usize,
+        /// Empty space left between neighbouring sprites
+        #[arg(long, default_value_t = PackOptions::default().padding)]
+        padding: usize,
+    },
+}
+
+#[derive(Args)]
+struct ConversionArgs {
+    /// Convert images row by row, keeping only a few rows in memory
+    #[arg(long)]
+    streaming: bool,
+    /// How colors of translucent pixels are stored in DATA
+    #[arg(long, value_enum, default_value_t = AlphaModeArg::Straight)]
+    alpha_mode: AlphaModeArg,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// Write a report of every converted file, as CSV if the path ends with .csv, or as JSON otherwise
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct FileArgs {
+    /// Convert every file in a directory, even those that haven't changed since the last conversion
+    #[arg(long)]
+    force: bool,
+    /// What to do when an output file already exists
+    #[arg(long, value_enum, default_value_t = OverwriteArg::Overwrite)]
+    overwrite: OverwriteArg,
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+impl From<FileArgs> for FileOptions {
+    fn from(value: FileArgs) -> Self {
+        FileOptions {
+            force: value.force,
+            overwrite: value.overwrite.into(),
+            scan: value.scan.into(),
+            observer: None,
+            thread_pool: None,
+        }
+    }
+}
+
+#[derive(Args)]
+struct ScanArgs {
+    /// Glob pattern of files to convert in a directory, relative to it, such as 'Graphics/Atlases/Gameplay/**'
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+    /// Glob pattern of files or directories to leave out, relative to the input directory, such as '**/Portraits/**'
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+    /// How many levels of subdirectories to look into, unlimited by default
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Include files and directories with names starting with a dot
+    #[arg(long)]
+    hidden: bool,
+    /// Follow symbolic links, which are skipped by default
+    #[arg(long)]
+    follow_symlinks: bool,
+}
+
+impl From<ScanArgs> for ScanOptions {
+    fn from(value: ScanArgs) -> Self {
+        ScanOptions {
+            include: value.include,
+            exclude: value.exclude,
+            max_depth: value.max_depth,
+            hidden: value.hidden,
+            follow_symlinks: value.follow_symlinks,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OverwriteArg {
+    Overwrite,
+    SkipExisting,
+    Backup,
+    FailIfExists,
+}
+
+impl From<OverwriteArg> for OverwritePolicy {
+    fn from(value: OverwriteArg) -> Self {
+        match value {
+            OverwriteArg::Overwrite => OverwritePolicy::Overwrite,
+            OverwriteArg::SkipExisting => OverwritePolicy::SkipExisting,
+            OverwriteArg::Backup => OverwritePolicy::Backup,
+            OverwriteArg::FailIfExists => OverwritePolicy::FailIfExists,
+        }
+    }
+}
+
+#[derive(Args)]
+struct DataArgs {
+    /// Maximum width or height of DATA images being decoded
+    #[arg(long, default_value_t = DataLimits::default().max_dimension)]
+    max_dimension: u32,
+    /// Maximum total number of pixels in DATA images being decoded
+    #[arg(long, default_value_t = DataLimits::default().max_pixels)]
+    max_pixels: u64,
+}
+
+impl DataArgs {
+    fn limits(&self) -> DataLimits {
+        DataLimits { max_dimension: self.max_dimension, max_pixels: self.max_pixels }
+    }
+
+    fn options(self, conversion: &ConversionArgs) -> DataToPngOptions {
+        DataToPngOptions {
+            limits: self.limits(),
+            streaming: conversion.streaming,
+            alpha_mode: conversion.alpha_mode.into(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct PngArgs {
+    /// Dithering applied when reducing 16-bit PNG samples to 8 bits
+    #[arg(long, value_enum, default_value_t = DitherArg::None)]
+    dither: DitherArg,
+    /// Color in RRGGBB or RRGGBBAA hex format, used for palette indices without a palette entry
+    #[arg(long, value_parser = parse_color)]
+    palette_fallback: Option<[u8; 4]>,
+}
+
+impl PngArgs {
+    fn options(self, conversion: &ConversionArgs) -> PngToDataOptions {
+        PngToDataOptions {
+            dither: self.dither.into(),
+            palette_fallback: self.palette_fallback,
+            streaming: conversion.streaming,
+            alpha_mode: conversion.alpha_mode.into(),
+        }
+    }
+}
+
+fn parse_color(value: &str) -> Result<[u8; 4], String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !matches!(hex.len(), 6 | 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("expected RRGGBB or RRGGBBAA hex color, got '{value}'"));
+    }
+
+    let mut color = [255; 4];
+    for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    Ok(color)
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DitherArg {
+    None,
+    Ordered,
+    FloydSteinberg,
+}
+
+impl From<DitherArg> for Dither {
+    fn from(value: DitherArg) -> Self {
+        match value {
+            DitherArg::None => Dither::None,
+            DitherArg::Ordered => Dither::Ordered,
+            DitherArg::FloydSteinberg => Dither::FloydSteinberg,
+        }
+    }
+}
 
-fn main() {
-    init_rayon();
-    
-    log!("Celeste converter v{}\n", env!("CARGO_PKG_VERSION"));
-
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        log!("Usage:");
-        log!("    celeste-converter [COMMAND] [INPUT] [OUTPUT]");
-        log!("Commands:");
-        log!("    data2png    Convert from Celeste DATA format into PNG");
-        log!("    png2data    Convert from PNG into Celeste DATA format");
-        return;
-    }
-
-    let command = args[1].as_str();
-    let input = PathBuf::from(&args[2]);
-    let output = if args.len() >= 4 { Some(PathBuf::from(&args[3])) } else { None };
-
-    let command_result = match command {
-        "data2png" => data_to_png(input, output),
-        "png2data" => png_to_data(input, output),
-        _ => Err(anyhow!("Unknown command {command}")),
+#[derive(Clone, Copy, ValueEnum)]
+enum AlphaModeArg {
+    Straight,
+    Premultiplied,
+}
+
+impl From<AlphaModeArg> for AlphaMode {
+    fn from(value: AlphaModeArg) -> Self {
+        match value {
+            AlphaModeArg::Straight => AlphaMode::Straight,
+            AlphaModeArg::Premultiplied => AlphaMode::Premultiplied,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    set_max_level(Some(cli.log_level()));
+    let logger = stderr_logger(cli.log_format == LogFormatArg::Json);
+    let progress = cli.progress_bar().map(Arc::new);
+    match progress.clone() {
+        // Messages would get mixed with the progress line otherwise
+        Some(progress) => set_logger(move |record| progress.suspend(|| logger(record))),
+        None => set_logger(logger),
+    }
+
+    let report_path = match &cli.command {
+        Command::Convert { report, .. } | Command::Data2png { report, .. } | Command::Png2data { report, .. } => report.report.clone(),
+        _ => None,
+    };
+    let report = report_path.as_ref().map(|_| Arc::new(Report::new()));
+
+    let mut observers: Vec<Arc<dyn Observer>> = Vec::new();
+    observers.extend(progress.map(|p| p as Arc<dyn Observer>));
+    observers.extend(report.clone().map(|r| r as Arc<dyn Observer>));
+    let observer: Option<Arc<dyn Observer>> = match observers.len() {
+        0 => None,
+        1 => observers.pop(),
+        _ => Some(Arc::new(Observers(observers))),
     };
+    let file_options = |file: FileArgs| FileOptions { observer: observer.clone(), ..file.into() };
 
-    if command_result.is_err() {
-        log!("Error: {}", command_result.unwrap_err());
+    if let Err(e) = init_rayon(cli.threads) {
+        log!(warn: "Failed to set up threads, using the existing ones: {}", e);
     }
+
+    log!("Celeste converter v{}", env!("CARGO_PKG_VERSION"));
+
+    let command_result = match cli.command {
+        Command::Convert { input, output, png, data, conversion, file, .. } => {
+            convert_auto(input, output, &data.options(&conversion), &png.options(&conversion), &file_options(file))
+        }
+        Command::Data2png { input, output, data, conversion, file, .. } => {
+            data_to_png(input, output, &data.options(&conversion), &file_options(file))
+        }
+        Command::Png2data { input, output, png, conversion, file, .. } => {
+            png_to_data(input, output, &png.options(&conversion), &file_options(file))
+        }
+        Command::Watch { input, output, poll_interval, debounce, png, data, conversion, file } => {
+            let options = WatchOptions {
+                poll_interval: Duration::from_millis(poll_interval),
+                debounce: Duration::from_millis(debounce),
+            };
+            // Watching only stops when the process gets interrupted
+            let stop = AtomicBool::new(false);
+            watch(&input, &output, &data.options(&conversion), &png.options(&conversion), &file_options(file), &options, &stop)
+        }
+        Command::Verify { input, data } => verify_data(input, &data.limits()),
+        Command::Slice { input, output } => slice_atlas(input, Some(output)),
+        Command::Pack { input, output, max_page_size, padding } => {
+            pack_atlas(input, Some(output), &PackOptions { max_page_size, padding })
+        }
+    };
+
+    let mut exit_code = match command_result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            log!(error: "{}", e);
+            ExitCode::FAILURE
+        }
+    };
+
+    // Report is still written when the command fails, as it lists files to retry
+    if let (Some(path), Some(report)) = (report_path, report) {
+        match report.save(&path) {
+            Ok(_) => log!("Report written into {}", path.display()),
+            Err(e) => {
+                log!(error: "Failed to write report into {}: {}", path.display(), e);
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+    }
+
+    exit_code
 }
@@ -0,0 +1,138 @@
+use crate::file::write_atomically;
+use crate::log;
+use crate::error::Result;
+use std::collections::HashMap;
+use std::fs::{read_to_string, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Name of the manifest file, which is kept in the output directory.
+pub const MANIFEST_FILE_NAME: &str = ".celeste-converter-manifest";
+
+/// Record of files converted into a directory, which lets later conversions skip unchanged files.
+///
+/// Stored as text, one file per line: content hash, size, modification time, options fingerprint,
+/// input path and output path.
+/// Paths are relative to the input and output directories respectively.
+#[derive(Default)]
+pub struct Manifest {
+    entries: HashMap<PathBuf, ManifestEntry>,
+}
+
+/// State of an input file at the time it was converted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub hash: u64,
+    pub size: u64,
+    /// Modification time in nanoseconds since Unix epoch.
+    pub modified: u128,
+    /// Fingerprint of the options the file was converted with.
+    pub options: u64,
+    /// Output path, relative to the output directory.
+    pub output: PathBuf,
+}
+
+impl Manifest {
+    /// Loads manifest from the directory, treating a missing or unreadable one as empty.
+    pub fn load(dir: &Path) -> Manifest {
+        let path = dir.join(MANIFEST_FILE_NAME);
+        let text = match read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return Manifest::default(),
+        };
+
+        let mut entries = HashMap::new();
+        for line in text.lines() {
+            match parse_line(line) {
+                Some((input, entry)) => { entries.insert(input, entry); }
+                None => {
+                    log!(warn: "Ignoring malformed manifest {}", path.display());
+                    return Manifest::default();
+                }
+            }
+        }
+
+        Manifest { entries }
+    }
+
+    /// Saves manifest into the directory. Entries with paths that can't be stored as text are left out.
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let mut lines: Vec<String> = self.entries
+            .iter()
+            .filter_map(|(input, e)| {
+                let input = input.to_str().filter(|p| !p.contains(['\t', '\n', '\r']))?;
+                let output = e.output.to_str().filter(|p| !p.contains(['\t', '\n', '\r']))?;
+                Some(format!("{:016x}\t{}\t{}\t{:016x}\t{}\t{}\n", e.hash, e.size, e.modified, e.options, input, output))
+            })
+            .collect();
+        lines.sort();
+
+        write_atomically(&dir.join(MANIFEST_FILE_NAME), false, |w| Ok(w.write_all(lines.concat().as_bytes())?))
+    }
+
+    pub fn get(&self, input: &Path) -> Option<&ManifestEntry> {
+        self.entries.get(input)
+    }
+
+    pub fn insert(&mut self, input: PathBuf, entry: ManifestEntry) {
+        self.entries.insert(input, entry);
+    }
+
+    pub fn remove(&mut self, input: &Path) -> Option<ManifestEntry> {
+        self.entries.remove(input)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, ManifestEntry)> {
+    let mut fields = line.split('\t');
+    let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let modified = fields.next()?.parse().ok()?;
+    let options = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let input = PathBuf::from(fields.next()?);
+    let output = PathBuf::from(fields.next()?);
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some((input, ManifestEntry { hash, size, modified, options, output }))
+}
+
+/// Reads file size and modification time, which are cheap to compare before resorting to content hash.
+pub fn file_stamp(path: &Path) -> Result<(u64, u128)> {
+    let metadata = path.metadata()?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    Ok((metadata.len(), modified))
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF29CE484222325;
+const FNV_PRIME: u64 = 0x100000001B3;
+
+/// Hashes file contents with 64-bit FNV-1a, which stays the same across builds and platforms.
+pub fn hash_file(path: &Path) -> Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = [0; 0x10000];
+    let mut hash = FNV_OFFSET_BASIS;
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        hash = hash_continue(hash, &buf[..len]);
+    }
+
+    Ok(hash)
+}
+
+/// Hashes bytes with 64-bit FNV-1a, the same way as [`hash_file`].
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    hash_continue(FNV_OFFSET_BASIS, bytes)
+}
+
+fn hash_continue(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
@@ -1,18 +1,83 @@
+use crate::data::TARGET_CHUNK_SIZE;
+use crate::dither::{dither_sixteen_bit, Dither};
 use crate::math::make_divisible_by;
 use crate::unpack::unpack;
-use anyhow::{bail, Result};
+use crate::error::{Error, Result};
 use png::{BitDepth, ColorType};
+use rayon::prelude::*;
+use std::fmt::{Display, Formatter};
 use std::io::Read;
 use BitDepth::*;
 use ColorType::*;
 
+/// Problem with PNG input, which prevents it from being decoded, or with PNG output, which prevents it from being encoded.
+#[derive(Debug)]
+pub enum PngError {
+    Decoding(png::DecodingError),
+    Encoding(png::EncodingError),
+    /// Image with indexed color type has no palette.
+    MissingPalette,
+    /// Image with indexed color type has 16-bit depth.
+    SixteenBitIndexed,
+    /// Palette has the given number of bytes, which don't make up whole RGB entries.
+    MalformedPalette(usize),
+    /// Palette has more entries than indices of the bit depth can address.
+    PaletteTooLarge { entries: usize, bit_depth: u8, max_entries: usize },
+    /// Color key in tRNS chunk is shorter than a pixel.
+    MalformedTransparency,
+    /// Pixel at the given index uses palette index without a palette entry.
+    PaletteIndexOutOfRange { pixel: u64, index: u8, entries: usize },
+    /// Image ends after the given number of rows.
+    MissingRows { rows: usize, height: usize },
+    /// Row is shorter than the image width.
+    ShortRow { row: usize, expected: usize, actual: usize },
+}
+
+impl Display for PngError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PngError::Decoding(e) => write!(f, "{e}"),
+            PngError::Encoding(e) => write!(f, "{e}"),
+            PngError::MissingPalette => write!(f, "Image with indexed color type is missing a palette"),
+            PngError::SixteenBitIndexed => write!(f, "Image with indexed color type can't have 16-bit depth"),
+            PngError::MalformedPalette(len) => write!(f, "Image has malformed palette of {len} bytes"),
+            PngError::PaletteTooLarge { entries, bit_depth, max_entries } => {
+                write!(f, "Image palette has {entries} entries, but {bit_depth}-bit indices only allow {max_entries}")
+            }
+            PngError::MalformedTransparency => write!(f, "Image has malformed tRNS chunk"),
+            PngError::PaletteIndexOutOfRange { pixel, index, entries } => {
+                write!(f, "Image uses palette index {index} at pixel {pixel}, but its palette only has {entries} entries")
+            }
+            PngError::MissingRows { rows, height } => write!(f, "Image ends after {rows} of {height} rows"),
+            PngError::ShortRow { row, expected, actual } => {
+                write!(f, "Image row {row} must have {expected} bytes of pixel data, but has {actual}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PngError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngError::Decoding(e) => Some(e),
+            PngError::Encoding(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 pub struct Png {
     pub width: usize,
     pub height: usize,
     pub color_type: ColorType,
     pub bit_depth: BitDepth,
+    /// Dithering applied when reducing 16-bit samples to 8 bits.
+    pub dither: Dither,
+    /// RGBA color used for palette indices without a palette entry, which are an error if not set.
+    pub palette_fallback: Option<[u8; 4]>,
     data: Vec<u8>,
     palette: Option<Vec<u8>>,
+    trns: Option<Vec<u8>>,
     bpp: usize,
     divisor: usize,
 }
@@ -26,10 +91,65 @@ impl Png {
         data: Vec<u8>,
         palette: Option<Vec<u8>>,
     ) -> Result<Png> {
-        if color_type == Indexed && palette.is_none() {
-            bail!("Image with indexed color type is missing a palette");
+        Self::with_trns(width, height, color_type, bit_depth, data, palette, None)
+    }
+
+    /// Creates an image with transparency defined by tRNS chunk,
+    /// which is either a palette alpha table or a single color key.
+    pub fn with_trns(
+        width: usize,
+        height: usize,
+        color_type: ColorType,
+        bit_depth: BitDepth,
+        data: Vec<u8>,
+        palette: Option<Vec<u8>>,
+        trns: Option<Vec<u8>>,
+    ) -> Result<Png> {
+        let expected_len = height * (width * bit_depth as usize * color_type.samples()).div_ceil(8);
+        if data.len() < expected_len {
+            return Err(Error::PixelDataLength { width, height, expected: expected_len, actual: data.len() });
         }
 
+        let png = Self::without_data(width, height, color_type, bit_depth, palette, trns)?;
+        Ok(Png { data, ..png })
+    }
+
+    /// Creates an image with parameters only, which don't depend on pixel data.
+    fn without_data(
+        width: usize,
+        height: usize,
+        color_type: ColorType,
+        bit_depth: BitDepth,
+        palette: Option<Vec<u8>>,
+        trns: Option<Vec<u8>>,
+    ) -> Result<Png> {
+        if color_type == Indexed {
+            // Palette must consist of whole RGB entries, which all have to be addressable by the indices
+            let palette_len = match &palette {
+                None => return Err(PngError::MissingPalette.into()),
+                Some(p) => p.len(),
+            };
+            if bit_depth == Sixteen {
+                return Err(PngError::SixteenBitIndexed.into());
+            }
+            let max_entries = 1 << bit_depth as usize;
+            if palette_len == 0 || palette_len % 3 != 0 {
+                return Err(PngError::MalformedPalette(palette_len).into());
+            }
+            if palette_len / 3 > max_entries {
+                return Err(PngError::PaletteTooLarge { entries: palette_len / 3, bit_depth: bit_depth as u8, max_entries }.into());
+            }
+        }
+
+        // Transparency is either a palette alpha table or a single color key,
+        // which has 2-byte samples for 16-bit images and 1-byte samples otherwise
+        let key_len = color_type.samples() * if bit_depth == Sixteen { 2 } else { 1 };
+        let trns = match (color_type, trns) {
+            (GrayscaleAlpha | Rgba, _) => None,
+            (Grayscale | Rgb, Some(t)) if t.len() < key_len => return Err(PngError::MalformedTransparency.into()),
+            (_, trns) => trns,
+        };
+
         // Divisor must account for BPP (bits-per-pixel) and picture width
         let bpp = bit_depth as usize * color_type.samples();
         let divisor = if bpp < 8 {
@@ -42,7 +162,7 @@ impl Png {
             1
         };
 
-        Ok(Png { width, height, color_type, bit_depth, data, palette, bpp, divisor })
+        Ok(Png { width, height, color_type, bit_depth, dither: Dither::None, palette_fallback: None, data: Vec::new(), palette, trns, bpp, divisor })
     }
 
     pub fn load<R: Read>(input: &mut R) -> Result<Png> {
@@ -56,21 +176,44 @@ impl Png {
         let color_type = frame_info.color_type;
         let bit_depth = frame_info.bit_depth;
         let palette = reader.info().palette.as_ref().map(|p| p.to_vec());
+        let trns = reader.info().trns.as_ref().map(|t| t.to_vec());
 
         // Leave only the first frame data
         data.truncate(frame_info.buffer_size());
 
-        Self::new(width, height, color_type, bit_depth, data, palette)
+        Self::with_trns(width, height, color_type, bit_depth, data, palette, trns)
+    }
+
+    /// Checks whether the image has transparency, either as alpha channel or defined by tRNS chunk.
+    pub fn has_alpha(&self) -> bool {
+        match (self.color_type, &self.trns) {
+            (GrayscaleAlpha | Rgba, _) => true,
+            // Palette alpha table may still leave all colors fully opaque
+            // Fallback color may be translucent as well
+            (Indexed, Some(trns)) => trns.iter().any(|&a| a != 255) || self.palette_fallback.is_some_and(|c| c[3] != 255),
+            (Indexed, None) => self.palette_fallback.is_some_and(|c| c[3] != 255),
+            (_, trns) => trns.is_some(),
+        }
+    }
+
+    /// Checks that every palette index used by the image has a palette entry, unless there's a fallback color.
+    pub fn check_palette(&self) -> Result<()> {
+        let palette_entries = match (&self.palette, self.color_type) {
+            (Some(palette), Indexed) if self.palette_fallback.is_none() => palette.len() / 3,
+            _ => return Ok(()),
+        };
+
+        self.chunks(TARGET_CHUNK_SIZE).par_iter().try_for_each(|c| c.check_palette(palette_entries))
     }
 
     /// Convert into a single chunk for further processing. 
-    pub fn as_chunk(&self) -> PngChunk {
+    pub fn as_chunk(&self) -> PngChunk<'_> {
         let len = self.width * self.height;
-        PngChunk { data: &self.data, len, span: len, png: self }
+        PngChunk { data: &self.data, len, span: len, start: 0, png: self }
     }
 
     /// Split into multiple chunks, useful for further parallel processing.
-    pub fn chunks(&self, target_len: usize) -> Vec<PngChunk> {
+    pub fn chunks(&self, target_len: usize) -> Vec<PngChunk<'_>> {
         // Adjust target chunk length to be divisible by the divisor 
         let len = make_divisible_by(target_len, self.divisor);
 
@@ -88,22 +231,96 @@ impl Png {
         let mut chunks = Vec::with_capacity(data_chunks.len());
         for i in 0..data_chunks.len() - 1 {
             let data_chunk = data_chunks[i];
-            chunks.push(PngChunk { data: data_chunk, len, span, png: self })
+            chunks.push(PngChunk { data: data_chunk, len, span, start: i * len, png: self })
         }
         if data_chunks.len() > 0 {
             let data_chunk = data_chunks[data_chunks.len() - 1];
             let remainder = (self.width * self.height) % len;
+            let start = (data_chunks.len() - 1) * len;
             let len = if remainder > 0 { remainder } else { len };
-            chunks.push(PngChunk { data: data_chunk, len, span, png: self })
+            chunks.push(PngChunk { data: data_chunk, len, span, start, png: self })
         }
         chunks
     }
 }
 
+/// Reader of PNG, which decodes one row at a time instead of the whole frame.
+///
+/// Interlaced images can't be decoded row by row, so they are still decoded whole.
+pub struct PngRows<R: Read> {
+    /// Image parameters, without pixel data of its own.
+    pub png: Png,
+    reader: png::Reader<R>,
+    /// Whole frame, only decoded for interlaced images.
+    frame: Option<Vec<u8>>,
+    row: usize,
+}
+
+impl<R: Read> PngRows<R> {
+    pub fn new(input: R) -> Result<PngRows<R>> {
+        let decoder = png::Decoder::new(input);
+        let mut reader = decoder.read_info()?;
+
+        let info = reader.info();
+        let width = info.width as usize;
+        let height = info.height as usize;
+        let interlaced = info.interlaced;
+        let palette = info.palette.as_ref().map(|p| p.to_vec());
+        let trns = info.trns.as_ref().map(|t| t.to_vec());
+        let (color_type, bit_depth) = reader.output_color_type();
+
+        let frame = if interlaced {
+            let mut data = vec![0; reader.output_buffer_size()];
+            let frame_info = reader.next_frame(&mut data)?;
+            data.truncate(frame_info.buffer_size());
+            Some(data)
+        } else {
+            None
+        };
+
+        let png = Png::without_data(width, height, color_type, bit_depth, palette, trns)?;
+        Ok(PngRows { png, reader, frame, row: 0 })
+    }
+
+    /// Returns the next row as a chunk, or nothing once all rows are read.
+    /// Fails if the row uses palette index without a palette entry, unless there's a fallback color.
+    pub fn next_row(&mut self) -> Result<Option<PngChunk<'_>>> {
+        if self.row >= self.png.height {
+            return Ok(None);
+        }
+
+        let line_len = (self.png.width * self.png.bpp).div_ceil(8);
+        let data = match &self.frame {
+            Some(frame) => &frame[self.row * line_len..(self.row + 1) * line_len],
+            None => match self.reader.next_row()? {
+                Some(row) => row.data(),
+                None => return Err(PngError::MissingRows { rows: self.row, height: self.png.height }.into()),
+            },
+        };
+        if data.len() < line_len {
+            return Err(PngError::ShortRow { row: self.row, expected: line_len, actual: data.len() }.into());
+        }
+
+        let width = self.png.width;
+        let start = self.row * width;
+        self.row += 1;
+
+        let chunk = PngChunk { data, len: width, span: width, start, png: &self.png };
+
+        // Palette is checked as rows come, since there's no whole image to check beforehand
+        if let (Some(palette), Indexed, None) = (&self.png.palette, self.png.color_type, self.png.palette_fallback) {
+            chunk.check_palette(palette.len() / 3)?;
+        }
+
+        Ok(Some(chunk))
+    }
+}
+
 pub struct PngChunk<'a> {
     pub data: &'a [u8],
     pub len: usize,
     span: usize,
+    start: usize,
     png: &'a Png,
 }
 
@@ -133,16 +350,14 @@ impl PngChunk<'_> {
     fn indexed_to_rgb(&self) -> Vec<u8> {
         let input = self.unpack();
         let mut output = vec![0; self.len * 3];
-        let palette = self.png.palette.as_ref().unwrap();
 
         for pixel in 0..self.len {
-            let palette_index = input[pixel] as usize;
-            let palette_offset = palette_index * 3;
+            let [r, g, b, _] = self.palette_color(input[pixel]);
 
             let offset = pixel * 3;
-            output[offset + 0] = palette[palette_offset + 0];
-            output[offset + 1] = palette[palette_offset + 1];
-            output[offset + 2] = palette[palette_offset + 2];
+            output[offset + 0] = r;
+            output[offset + 1] = g;
+            output[offset + 2] = b;
         }
 
         output
@@ -151,17 +366,15 @@ impl PngChunk<'_> {
     fn indexed_to_rgba(&self) -> Vec<u8> {
         let input = self.unpack();
         let mut output = vec![0; self.len * 4];
-        let palette = self.png.palette.as_ref().unwrap();
 
         for pixel in 0..self.len {
-            let palette_index = input[pixel] as usize;
-            let palette_offset = palette_index * 3;
+            let [r, g, b, a] = self.palette_color(input[pixel]);
 
             let offset = pixel * 4;
-            output[offset + 0] = palette[palette_offset + 0];
-            output[offset + 1] = palette[palette_offset + 1];
-            output[offset + 2] = palette[palette_offset + 2];
-            output[offset + 3] = 255;
+            output[offset + 0] = r;
+            output[offset + 1] = g;
+            output[offset + 2] = b;
+            output[offset + 3] = a;
         }
 
         output
@@ -189,14 +402,16 @@ impl PngChunk<'_> {
         let mut output = vec![0; self.len * 4];
 
         let multiplier = self.grayscale_multiplier();
+        let transparent = self.color_key_matches();
         for pixel in 0..self.len {
             let grey = input[pixel] * multiplier;
+            let alpha = if transparent.as_ref().is_some_and(|t| t[pixel]) { 0 } else { 255 };
 
             let output_offset = pixel * 4;
             output[output_offset + 0] = grey;
             output[output_offset + 1] = grey;
             output[output_offset + 2] = grey;
-            output[output_offset + 3] = 255;
+            output[output_offset + 3] = alpha;
         }
 
         output
@@ -248,17 +463,19 @@ impl PngChunk<'_> {
         let input = self.unpack();
         let mut output = vec![0; self.len * 4];
 
+        let transparent = self.color_key_matches();
         for pixel in 0..self.len {
             let input_offset = pixel * 3;
             let r = input[input_offset];
             let g = input[input_offset + 1];
             let b = input[input_offset + 2];
+            let alpha = if transparent.as_ref().is_some_and(|t| t[pixel]) { 0 } else { 255 };
 
             let output_offset = pixel * 4;
             output[output_offset + 0] = r;
             output[output_offset + 1] = g;
             output[output_offset + 2] = b;
-            output[output_offset + 3] = 255;
+            output[output_offset + 3] = alpha;
         }
 
         output
@@ -287,6 +504,31 @@ impl PngChunk<'_> {
         self.unpack()
     }
 
+    /// Looks up RGBA color of the palette entry.
+    /// Missing entries get the fallback color, or opaque black if the palette wasn't checked beforehand.
+    fn palette_color(&self, index: u8) -> [u8; 4] {
+        let index = index as usize;
+        let palette = self.png.palette.as_deref().unwrap_or_default();
+        match palette.get(index * 3..index * 3 + 3) {
+            Some(&[r, g, b]) => {
+                // Palette entries without corresponding tRNS alpha are fully opaque
+                let alphas = self.png.trns.as_deref().unwrap_or_default();
+                [r, g, b, alphas.get(index).copied().unwrap_or(255)]
+            }
+            _ => self.png.palette_fallback.unwrap_or([0, 0, 0, 255]),
+        }
+    }
+
+    /// Checks that every palette index used by the chunk has a palette entry.
+    fn check_palette(&self, palette_entries: usize) -> Result<()> {
+        match self.unpack().into_iter().take(self.len).enumerate().find(|&(_, i)| i as usize >= palette_entries) {
+            Some((pixel, index)) => {
+                Err(PngError::PaletteIndexOutOfRange { pixel: (self.start + pixel) as u64, index, entries: palette_entries }.into())
+            }
+            None => Ok(()),
+        }
+    }
+
     fn grayscale_multiplier(&self) -> u8 {
         match self.png.bit_depth {
             One => 255,
@@ -296,7 +538,28 @@ impl PngChunk<'_> {
         }
     }
 
+    /// Finds pixels matching the transparent color key from tRNS chunk, if there is one.
+    fn color_key_matches(&self) -> Option<Vec<bool>> {
+        let trns = self.png.trns.as_ref()?;
+        let samples = self.png.color_type.samples();
+
+        // Color key is matched against raw sample values, before they get scaled to 8 bits
+        let (key, raw): (Vec<u16>, Vec<u16>) = if self.png.bit_depth == Sixteen {
+            let to_u16 = |s: &[u8]| u16::from_be_bytes([s[0], s[1]]);
+            (trns.chunks_exact(2).take(samples).map(to_u16).collect(), self.data.chunks_exact(2).map(to_u16).collect())
+        } else {
+            (trns.iter().take(samples).map(|&s| s as u16).collect(), self.unpack().into_iter().map(u16::from).collect())
+        };
+
+        Some(raw.chunks_exact(samples).take(self.len).map(|p| p == key.as_slice()).collect())
+    }
+
     fn unpack(&self) -> Vec<u8> {
+        if self.png.bit_depth == Sixteen && self.png.dither != Dither::None {
+            let samples = self.png.color_type.samples();
+            return dither_sixteen_bit(self.data, samples, self.png.width, self.start, self.png.dither);
+        }
+
         unpack(self.data, self.len, self.span, self.png.bit_depth)
     }
 }
@@ -0,0 +1,194 @@
+use crate::error::Error;
+use std::io::{stderr, IsTerminal, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Receives events about files being converted, such as to show progress.
+///
+/// Events of different files come from multiple threads at once.
+pub trait Observer: Send + Sync {
+    /// Conversion of the given number of files, with total size in bytes, has started.
+    fn started(&self, _files: usize, _bytes: u64) {}
+
+    /// Conversion of the file has started.
+    fn file_started(&self, _input: &Path) {}
+
+    /// File of the given size was converted into the output, or skipped as unchanged or already existing.
+    fn file_finished(&self, _input: &Path, _output: &Path, _bytes: u64, _skipped: bool) {}
+
+    /// File of the given size failed to convert.
+    fn file_failed(&self, _input: &Path, _bytes: u64, _error: &Error) {}
+
+    /// Conversion of all files has finished.
+    fn finished(&self) {}
+}
+
+/// Observer passing every event to each of the observers.
+pub struct Observers(pub Vec<Arc<dyn Observer>>);
+
+impl Observer for Observers {
+    fn started(&self, files: usize, bytes: u64) {
+        self.0.iter().for_each(|o| o.started(files, bytes));
+    }
+
+    fn file_started(&self, input: &Path) {
+        self.0.iter().for_each(|o| o.file_started(input));
+    }
+
+    fn file_finished(&self, input: &Path, output: &Path, bytes: u64, skipped: bool) {
+        self.0.iter().for_each(|o| o.file_finished(input, output, bytes, skipped));
+    }
+
+    fn file_failed(&self, input: &Path, bytes: u64, error: &Error) {
+        self.0.iter().for_each(|o| o.file_failed(input, bytes, error));
+    }
+
+    fn finished(&self) {
+        self.0.iter().for_each(|o| o.finished());
+    }
+}
+
+/// Observer showing a live progress line in the terminal.
+pub struct ProgressBar {
+    state: Mutex<ProgressState>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    start: Option<Instant>,
+    last_render: Option<Instant>,
+    total_files: usize,
+    total_bytes: u64,
+    done_files: usize,
+    done_bytes: u64,
+    /// Length of the line currently displayed, if any.
+    line_len: usize,
+}
+
+impl ProgressBar {
+    const RENDER_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub fn new() -> ProgressBar {
+        ProgressBar { state: Mutex::new(ProgressState::default()) }
+    }
+
+    /// Creates the progress bar only when stderr is a terminal, as the progress line would garble anything else.
+    pub fn for_terminal() -> Option<ProgressBar> {
+        stderr().is_terminal().then(ProgressBar::new)
+    }
+
+    /// Hides the progress line while running the function, such as to log a message, then shows it again.
+    pub fn suspend<F: FnOnce()>(&self, f: F) {
+        let mut state = self.lock();
+        state.clear();
+        f();
+        if state.start.is_some() {
+            state.render();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ProgressState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn file_done(&self, bytes: u64) {
+        let mut state = self.lock();
+        state.done_files += 1;
+        state.done_bytes += bytes;
+        if state.last_render.is_none_or(|t| t.elapsed() >= Self::RENDER_INTERVAL) {
+            state.render();
+        }
+    }
+}
+
+impl Default for ProgressBar {
+    fn default() -> Self {
+        ProgressBar::new()
+    }
+}
+
+impl Observer for ProgressBar {
+    fn started(&self, files: usize, bytes: u64) {
+        let mut state = self.lock();
+        *state = ProgressState { start: Some(Instant::now()), total_files: files, total_bytes: bytes, ..Default::default() };
+        state.render();
+    }
+
+    fn file_finished(&self, _input: &Path, _output: &Path, bytes: u64, _skipped: bool) {
+        self.file_done(bytes);
+    }
+
+    fn file_failed(&self, _input: &Path, bytes: u64, _error: &Error) {
+        self.file_done(bytes);
+    }
+
+    fn finished(&self) {
+        // Final state stays on its own line
+        let mut state = self.lock();
+        state.render();
+        let _ = writeln!(stderr());
+        *state = ProgressState::default();
+    }
+}
+
+impl ProgressState {
+    fn render(&mut self) {
+        let elapsed = self.start.map_or(Duration::ZERO, |s| s.elapsed());
+        let mut line = format!(
+            "{}/{} files, {} of {}",
+            self.done_files,
+            self.total_files,
+            format_bytes(self.done_bytes),
+            format_bytes(self.total_bytes)
+        );
+
+        // Throughput is too noisy to show during the first moments
+        if self.done_bytes > 0 && elapsed >= Duration::from_millis(500) {
+            let rate = self.done_bytes as f64 / elapsed.as_secs_f64();
+            let eta = Duration::from_secs_f64(self.total_bytes.saturating_sub(self.done_bytes) as f64 / rate);
+            line += &format!(", {}/s, ETA {}", format_bytes(rate as u64), format_duration(eta));
+        }
+
+        // Leftovers of a longer previous line get covered with spaces
+        let padding = self.line_len.saturating_sub(line.len());
+        let mut stderr = stderr().lock();
+        let _ = write!(stderr, "\r{}{}", line, " ".repeat(padding));
+        let _ = stderr.flush();
+        self.line_len = line.len();
+        self.last_render = Some(Instant::now());
+    }
+
+    fn clear(&mut self) {
+        if self.line_len > 0 {
+            let mut stderr = stderr().lock();
+            let _ = write!(stderr, "\r{}\r", " ".repeat(self.line_len));
+            let _ = stderr.flush();
+            self.line_len = 0;
+        }
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
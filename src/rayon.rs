@@ -1,8 +1,24 @@
-use rayon::ThreadPoolBuilder;
+use crate::error::Result;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
-pub fn init_rayon() {
+/// Builds a thread pool with the given number of threads, or one per CPU when it's 0.
+pub fn build_pool(threads: usize) -> Result<ThreadPool> {
+    Ok(named_threads(threads).build()?)
+}
+
+/// Sets up the global thread pool with the given number of threads, or one per CPU when it's 0.
+///
+/// A single thread is the current one, which then does all the work in a predictable order.
+/// Fails if the global thread pool was already set up, such as by the host application, which can still be used.
+pub fn init_rayon(threads: usize) -> Result<()> {
+    let builder = named_threads(threads);
+    let builder = if threads == 1 { builder.use_current_thread() } else { builder };
+    builder.build_global()?;
+    Ok(())
+}
+
+fn named_threads(threads: usize) -> ThreadPoolBuilder {
     ThreadPoolBuilder::new()
+        .num_threads(threads)
         .thread_name(|i| format!("thread-{}", i))
-        .build_global()
-        .unwrap();
 }
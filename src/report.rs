@@ -0,0 +1,263 @@
+use crate::convert::{self, Format};
+use crate::data::{self, DataLimits};
+use crate::log::json_string;
+use crate::progress::Observer;
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::fs::{metadata, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Format of the written report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    /// Picks the format by extension of the report path, which is JSON unless it's `.csv`.
+    pub fn from_path(path: &Path) -> ReportFormat {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ReportFormat::Csv,
+            _ => ReportFormat::Json,
+        }
+    }
+}
+
+/// What happened to a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportStatus {
+    Converted,
+    Skipped,
+    Failed,
+}
+
+impl ReportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportStatus::Converted => "converted",
+            ReportStatus::Skipped => "skipped",
+            ReportStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Entry of a single file in the report. Fields that couldn't be found out are left empty.
+#[derive(Clone, Debug)]
+pub struct ReportEntry {
+    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+    pub status: ReportStatus,
+    /// Format of the input, which gets converted into the other one.
+    pub input_format: Option<Format>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub has_alpha: Option<bool>,
+    pub input_bytes: u64,
+    pub output_bytes: Option<u64>,
+    pub duration: Duration,
+    pub error: Option<String>,
+}
+
+impl ReportEntry {
+    /// Size of the input relative to the output, which is above 1 when the output is smaller.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        self.output_bytes.filter(|&b| b > 0).map(|b| self.input_bytes as f64 / b as f64)
+    }
+
+    /// Direction of the conversion, named after the command doing it.
+    pub fn direction(&self) -> Option<&'static str> {
+        self.input_format.map(|f| match f {
+            Format::Data => "data2png",
+            Format::Png => "png2data",
+        })
+    }
+}
+
+/// Observer collecting a report entry for every converted file.
+#[derive(Default)]
+pub struct Report {
+    started: Mutex<HashMap<PathBuf, Instant>>,
+    entries: Mutex<Vec<ReportEntry>>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+
+    /// Entries of all files so far, ordered by input path.
+    pub fn entries(&self) -> Vec<ReportEntry> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        entries.sort_by(|a, b| a.input.cmp(&b.input));
+        entries
+    }
+
+    /// Writes the report into the file, in format picked by its extension.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut output = BufWriter::new(File::create(path)?);
+        self.write(&mut output, ReportFormat::from_path(path))?;
+        output.flush()?;
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W, format: ReportFormat) -> Result<()> {
+        match format {
+            ReportFormat::Json => write_json(output, &self.entries()),
+            ReportFormat::Csv => write_csv(output, &self.entries()),
+        }
+    }
+
+    fn add(&self, input: &Path, output: Option<&Path>, status: ReportStatus, bytes: u64, error: Option<&Error>) {
+        let started = self.started.lock().unwrap_or_else(|e| e.into_inner()).remove(input);
+        let duration = started.map_or(Duration::ZERO, |s| s.elapsed());
+
+        // Dimensions are taken from the DATA side, as it states whether there's alpha explicitly
+        let input_info = image_info(input);
+        let output_info = output.filter(|_| status != ReportStatus::Failed).and_then(image_info);
+        let info = match (input_info, output_info) {
+            (Some(i), _) if i.format == Format::Data => Some(i),
+            (_, Some(o)) if o.format == Format::Data => Some(o),
+            (i, o) => i.or(o),
+        };
+
+        let entry = ReportEntry {
+            input: input.to_path_buf(),
+            output: output.map(Path::to_path_buf),
+            status,
+            input_format: input_info.map(|i| i.format),
+            width: info.map(|i| i.width),
+            height: info.map(|i| i.height),
+            has_alpha: info.map(|i| i.has_alpha),
+            input_bytes: bytes,
+            output_bytes: output.filter(|_| status != ReportStatus::Failed).and_then(|o| Some(metadata(o).ok()?.len())),
+            duration,
+            error: error.map(|e| e.innermost().to_string()),
+        };
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).push(entry);
+    }
+}
+
+impl Observer for Report {
+    fn file_started(&self, input: &Path) {
+        self.started.lock().unwrap_or_else(|e| e.into_inner()).insert(input.to_path_buf(), Instant::now());
+    }
+
+    fn file_finished(&self, input: &Path, output: &Path, bytes: u64, skipped: bool) {
+        let status = if skipped { ReportStatus::Skipped } else { ReportStatus::Converted };
+        self.add(input, Some(output), status, bytes, None);
+    }
+
+    fn file_failed(&self, input: &Path, bytes: u64, error: &Error) {
+        self.add(input, None, ReportStatus::Failed, bytes, Some(error));
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ImageInfo {
+    format: Format,
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+}
+
+/// Reads format and dimensions of the image from its header, without decoding it.
+fn image_info(path: &Path) -> Option<ImageInfo> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    let mut header = Vec::new();
+    (&mut file).take(16).read_to_end(&mut header).ok()?;
+
+    match convert::detect_format(&header, len)? {
+        Format::Data => {
+            let (width, height, has_alpha) = data::read_header(&mut header.as_slice(), &DataLimits::default()).ok()?;
+            Some(ImageInfo { format: Format::Data, width, height, has_alpha })
+        }
+        Format::Png => {
+            let file = File::open(path).ok()?;
+            let reader = png::Decoder::new(BufReader::new(file)).read_info().ok()?;
+            let info = reader.info();
+            let has_alpha = info.color_type.samples() % 2 == 0 || info.trns.is_some();
+            Some(ImageInfo { format: Format::Png, width: info.width, height: info.height, has_alpha })
+        }
+    }
+}
+
+const COLUMNS: [&str; 12] = [
+    "input",
+    "output",
+    "status",
+    "direction",
+    "width",
+    "height",
+    "has_alpha",
+    "input_bytes",
+    "output_bytes",
+    "compression_ratio",
+    "duration_ms",
+    "error",
+];
+
+/// Values of the entry in the order of columns, which are empty when not known.
+fn values(entry: &ReportEntry) -> [Option<String>; 12] {
+    [
+        Some(entry.input.to_string_lossy().to_string()),
+        entry.output.as_ref().map(|o| o.to_string_lossy().to_string()),
+        Some(entry.status.as_str().to_string()),
+        entry.direction().map(str::to_string),
+        entry.width.map(|w| w.to_string()),
+        entry.height.map(|h| h.to_string()),
+        entry.has_alpha.map(|a| a.to_string()),
+        Some(entry.input_bytes.to_string()),
+        entry.output_bytes.map(|b| b.to_string()),
+        entry.compression_ratio().map(|r| format!("{:.3}", r)),
+        Some(format!("{:.3}", entry.duration.as_secs_f64() * 1000.0)),
+        entry.error.clone(),
+    ]
+}
+
+fn write_json<W: Write>(output: &mut W, entries: &[ReportEntry]) -> Result<()> {
+    // Only paths, direction, status and error are strings, the rest are numbers or booleans
+    const STRING_COLUMNS: [usize; 5] = [0, 1, 2, 3, 11];
+
+    writeln!(output, "[")?;
+    for (i, entry) in entries.iter().enumerate() {
+        let fields: Vec<String> = values(entry)
+            .into_iter()
+            .enumerate()
+            .map(|(column, value)| {
+                let value = match value {
+                    None => "null".to_string(),
+                    Some(v) if STRING_COLUMNS.contains(&column) => json_string(&v),
+                    Some(v) => v,
+                };
+                format!("{}: {}", json_string(COLUMNS[column]), value)
+            })
+            .collect();
+        let separator = if i + 1 < entries.len() { "," } else { "" };
+        writeln!(output, "  {{{}}}{}", fields.join(", "), separator)?;
+    }
+    writeln!(output, "]")?;
+    Ok(())
+}
+
+fn write_csv<W: Write>(output: &mut W, entries: &[ReportEntry]) -> Result<()> {
+    writeln!(output, "{}", COLUMNS.join(","))?;
+    for entry in entries {
+        let fields: Vec<String> = values(entry).into_iter().map(|v| csv_field(&v.unwrap_or_default())).collect();
+        writeln!(output, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// Quotes the field when it contains characters that are special to CSV.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
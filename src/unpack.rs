@@ -1,5 +1,6 @@
 use BitDepth::*;
 use png::BitDepth;
+use crate::dither::round_sixteen_bit;
 use crate::math::make_divisible_by;
 
 pub fn unpack(data: &[u8], len: usize, span: usize, bit_depth: BitDepth) -> Vec<u8> {
@@ -70,10 +71,5 @@ fn unpack_eight_bit(data: &[u8]) -> Vec<u8> {
 }
 
 fn unpack_sixteen_bit(data: &[u8]) -> Vec<u8> {
-    let mut result = vec![0; data.len() / 2];
-    for i in 0..result.len() {
-        let offset = i * 2;
-        result[i] = data[offset];
-    }
-    result
+    round_sixteen_bit(data)
 }
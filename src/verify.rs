@@ -0,0 +1,197 @@
+use crate::data::DataLimits;
+use crate::error::Result;
+use std::fmt::{Display, Formatter};
+use std::io::{BufReader, ErrorKind, Read};
+
+/// Verification stops after finding this many issues, as they likely all stem from the same problem.
+const MAX_ISSUES: usize = 100;
+
+/// Outcome of DATA verification.
+pub struct Verification {
+    pub width: u32,
+    pub height: u32,
+    pub has_alpha: bool,
+    pub issues: Vec<Issue>,
+}
+
+impl Verification {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Problem found in DATA, along with the position it was found at.
+pub struct Issue {
+    /// Byte offset from the start of the input.
+    pub offset: u64,
+    /// Index of the first affected pixel, if the problem lies within the pixel stream.
+    pub pixel: Option<u64>,
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IssueKind {
+    /// Input ends before the header does.
+    TruncatedHeader,
+    /// Alpha channel flag has a value other than 0 or 1.
+    InvalidAlphaFlag(u8),
+    /// Image has zero width or height.
+    ZeroDimension,
+    /// Image dimensions exceed the limits.
+    TooLarge { width: u32, height: u32 },
+    /// Run has RLE count of 0.
+    ZeroRunLength,
+    /// Run continues past the last pixel of the image.
+    RunOverflow { run_len: u8, remaining: u64 },
+    /// Input ends before all pixels are covered by runs.
+    TruncatedStream { missing_pixels: u64 },
+    /// Input has extra bytes after the last run.
+    TrailingBytes(u64),
+    /// Verification was stopped early because of too many issues.
+    TooManyIssues,
+}
+
+impl Display for IssueKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IssueKind::TruncatedHeader => write!(f, "header is truncated"),
+            IssueKind::InvalidAlphaFlag(value) => write!(f, "alpha channel flag has invalid value of {value}"),
+            IssueKind::ZeroDimension => write!(f, "image has zero width or height"),
+            IssueKind::TooLarge { width, height } => write!(f, "image of {width}x{height} exceeds the size limits"),
+            IssueKind::ZeroRunLength => write!(f, "RLE count has value of 0"),
+            IssueKind::RunOverflow { run_len, remaining } => {
+                write!(f, "run of {run_len} pixels overflows the image, which only has {remaining} pixels left")
+            }
+            IssueKind::TruncatedStream { missing_pixels } => {
+                write!(f, "pixel data is truncated, {missing_pixels} pixels are missing")
+            }
+            IssueKind::TrailingBytes(count) => write!(f, "{count} unexpected bytes follow the last run"),
+            IssueKind::TooManyIssues => write!(f, "verification stopped after too many issues"),
+        }
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.pixel {
+            Some(pixel) => write!(f, "byte {}, pixel {}: {}", self.offset, pixel, self.kind),
+            None => write!(f, "byte {}: {}", self.offset, self.kind),
+        }
+    }
+}
+
+/// Checks DATA for problems without decoding it, using default limits.
+///
+/// Only I/O failures are returned as errors, any problems with the data itself are reported as issues.
+pub fn verify<R: Read>(input: &mut R) -> Result<Verification> {
+    verify_with_limits(input, &DataLimits::default())
+}
+
+/// Checks DATA for problems without decoding it, reporting images exceeding the limits the same as decoding does.
+pub fn verify_with_limits<R: Read>(input: &mut R, limits: &DataLimits) -> Result<Verification> {
+    let mut reader = ByteReader { input: BufReader::new(input), offset: 0 };
+    let mut issues = Vec::new();
+
+    // Check header
+    let mut header = [0; 9];
+    for byte in &mut header {
+        match reader.next()? {
+            Some(value) => *byte = value,
+            None => {
+                issues.push(Issue { offset: reader.offset, pixel: None, kind: IssueKind::TruncatedHeader });
+                return Ok(Verification { width: 0, height: 0, has_alpha: false, issues });
+            }
+        }
+    }
+
+    let width = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    let has_alpha = header[8] != 0;
+    if header[8] > 1 {
+        issues.push(Issue { offset: 8, pixel: None, kind: IssueKind::InvalidAlphaFlag(header[8]) });
+    }
+    if width == 0 || height == 0 {
+        issues.push(Issue { offset: 0, pixel: None, kind: IssueKind::ZeroDimension });
+    }
+
+    if !limits.allow(width, height) {
+        issues.push(Issue { offset: 0, pixel: None, kind: IssueKind::TooLarge { width, height } });
+    }
+    let pixel_count = width as u64 * height as u64;
+
+    // Check runs
+    let sample_len = if has_alpha { 2 } else { 4 };
+    let mut pixel = 0;
+    while pixel < pixel_count {
+        if issues.len() >= MAX_ISSUES {
+            issues.push(Issue { offset: reader.offset, pixel: Some(pixel), kind: IssueKind::TooManyIssues });
+            return Ok(Verification { width, height, has_alpha, issues });
+        }
+
+        let run_offset = reader.offset;
+        let mut sample = [0; 5];
+        let mut complete = reader.read(&mut sample[..sample_len])?;
+        if complete && has_alpha && sample[1] != 0 {
+            complete = reader.read(&mut sample[2..])?;
+        }
+        if !complete {
+            let kind = IssueKind::TruncatedStream { missing_pixels: pixel_count - pixel };
+            issues.push(Issue { offset: run_offset, pixel: Some(pixel), kind });
+            return Ok(Verification { width, height, has_alpha, issues });
+        }
+
+        let run_len = sample[0];
+        if run_len == 0 {
+            issues.push(Issue { offset: run_offset, pixel: Some(pixel), kind: IssueKind::ZeroRunLength });
+            continue;
+        }
+
+        let remaining = pixel_count - pixel;
+        if run_len as u64 > remaining {
+            let kind = IssueKind::RunOverflow { run_len, remaining };
+            issues.push(Issue { offset: run_offset, pixel: Some(pixel), kind });
+        }
+
+        pixel += (run_len as u64).min(remaining);
+    }
+
+    // Check for trailing garbage
+    let trailing_offset = reader.offset;
+    let trailing_len = reader.skip_to_end()?;
+    if trailing_len > 0 {
+        issues.push(Issue { offset: trailing_offset, pixel: None, kind: IssueKind::TrailingBytes(trailing_len) });
+    }
+
+    Ok(Verification { width, height, has_alpha, issues })
+}
+
+/// Reader keeping track of its offset, which treats reaching the end as a normal outcome.
+struct ByteReader<R: Read> {
+    input: BufReader<R>,
+    offset: u64,
+}
+
+impl<R: Read> ByteReader<R> {
+    fn next(&mut self) -> Result<Option<u8>> {
+        let mut buf = [0];
+        Ok(if self.read(&mut buf)? { Some(buf[0]) } else { None })
+    }
+
+    /// Fills the buffer completely, returns `false` if the input ends before that.
+    fn read(&mut self, buf: &mut [u8]) -> Result<bool> {
+        match self.input.read_exact(buf) {
+            Ok(_) => {
+                self.offset += buf.len() as u64;
+                Ok(true)
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn skip_to_end(&mut self) -> Result<u64> {
+        let len = std::io::copy(&mut self.input, &mut std::io::sink())?;
+        self.offset += len;
+        Ok(len)
+    }
+}
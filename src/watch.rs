@@ -0,0 +1,232 @@
+use crate::convert::{DataToPngOptions, Format, PngToDataOptions};
+use crate::file::{
+    convert_auto, convert_file_auto, mirrored_output_path, options_fingerprint, scan_dir, FileOptions, FileOutcome,
+    ScanOptions,
+};
+use crate::log;
+use crate::manifest::{file_stamp, hash_file, Manifest, ManifestEntry};
+use crate::error::{Error, Result};
+use pathdiff::diff_paths;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, remove_file, rename};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Options for watching a directory.
+#[derive(Clone, Copy, Debug)]
+pub struct WatchOptions {
+    /// How often the input directory is checked for changes.
+    pub poll_interval: Duration,
+    /// How long the input directory must stay unchanged before changes get converted,
+    /// so that a burst of saves only causes a single conversion.
+    pub debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions { poll_interval: Duration::from_millis(500), debounce: Duration::from_millis(300) }
+    }
+}
+
+/// Size and modification time of every DATA and PNG file in the directory.
+type Snapshot = HashMap<PathBuf, (u64, u128)>;
+
+/// Converts the input directory, then keeps converting files as they are changed, until stopped.
+///
+/// Outputs of deleted files are removed, while outputs of renamed files are moved along with them.
+pub fn watch(
+    input: &Path,
+    output: &Path,
+    data_options: &DataToPngOptions,
+    png_options: &PngToDataOptions,
+    file_options: &FileOptions,
+    options: &WatchOptions,
+    stop: &AtomicBool,
+) -> Result<()> {
+    if !input.is_dir() {
+        return Err(Error::InputNotDirectory(input.to_path_buf()));
+    }
+    // Outputs would get converted back otherwise, over and over again
+    create_dir_all(output)?;
+    if output.canonicalize()?.starts_with(input.canonicalize()?) {
+        return Err(Error::OutputInsideInput);
+    }
+
+    // Convert whatever changed since the last time, which is not an error for the watch to continue
+    if let Err(e) = convert_auto(input.into(), Some(output.into()), data_options, png_options, file_options) {
+        log!(error: "{}", e);
+    }
+
+    // Outputs of the initial conversion are known from the manifest
+    let mut manifest = Manifest::load(output);
+    let mut known = snapshot(input, &file_options.scan)?;
+    let mut outputs: HashMap<PathBuf, PathBuf> = known
+        .keys()
+        .filter_map(|item| {
+            let entry = manifest.get(&diff_paths(item, input)?)?;
+            Some((item.clone(), output.join(&entry.output)))
+        })
+        .collect();
+
+    log!("Watching {} for changes...", input.display());
+    'poll: while !stop.load(Ordering::Relaxed) {
+        sleep(options.poll_interval);
+        let Some(mut current) = try_snapshot(input, &file_options.scan) else { continue };
+        if current == known {
+            continue;
+        }
+
+        // Wait for the changes to settle down
+        loop {
+            sleep(options.debounce);
+            if stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let Some(next) = try_snapshot(input, &file_options.scan) else { continue 'poll };
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+
+        apply_changes(input, output, &known, &current, &mut outputs, &mut manifest, |item, item_output| {
+            let (size, modified) = file_stamp(item)?;
+            let (item_output, format, outcome) =
+                file_options.install(|| convert_file_auto(item, item_output, data_options, png_options, file_options))?;
+            if outcome == FileOutcome::SkippedExisting {
+                return Ok((item_output, None));
+            }
+            let entry = ManifestEntry {
+                hash: hash_file(item)?,
+                size,
+                modified,
+                options: options_fingerprint(format, data_options, png_options),
+                output: relative_path(output, &item_output),
+            };
+            Ok((item_output, Some(entry)))
+        });
+
+        // Next conversion of the directory skips whatever was converted while watching
+        if let Err(e) = manifest.save(output) {
+            log!(warn: "Failed to save manifest into {}: {}", output.display(), e);
+        }
+        known = current;
+    }
+
+    Ok(())
+}
+
+fn apply_changes(
+    input: &Path,
+    output: &Path,
+    known: &Snapshot,
+    current: &Snapshot,
+    outputs: &mut HashMap<PathBuf, PathBuf>,
+    manifest: &mut Manifest,
+    convert_fn: impl Fn(&PathBuf, &Path) -> Result<(PathBuf, Option<ManifestEntry>)>,
+) {
+    let mut deleted: Vec<&PathBuf> = known.keys().filter(|p| !current.contains_key(*p)).collect();
+    let mut added: Vec<&PathBuf> = current.keys().filter(|p| !known.contains_key(*p)).collect();
+    let modified: Vec<&PathBuf> = current.keys().filter(|p| known.get(*p).is_some_and(|s| s != &current[*p])).collect();
+    deleted.sort();
+    added.sort();
+
+    // Renamed files keep their size and modification time, so their outputs can be moved instead
+    deleted.retain(|&old| {
+        let renamed = added.iter().position(|new| known[old] == current[*new] && old.extension() == new.extension());
+        let Some(index) = renamed else { return true };
+        let new = added.remove(index);
+
+        let old_output = outputs.remove(old).unwrap_or_else(|| guess_output_path(input, output, old));
+        let new_output = mirrored_output_path(input, output, new).with_extension(old_output.extension().unwrap_or_default());
+        match move_file(&old_output, &new_output) {
+            Ok(_) => {
+                log!("Moved {} to {}", old_output.display(), new_output.display());
+                if let Some(entry) = manifest.remove(&relative_path(input, old)) {
+                    let entry = ManifestEntry { output: relative_path(output, &new_output), ..entry };
+                    manifest.insert(relative_path(input, new), entry);
+                }
+                outputs.insert(new.clone(), new_output);
+                false
+            }
+            Err(e) => {
+                // Converting the file again still gets the output where it belongs
+                log!(warn: "Failed to move {}: {}", old_output.display(), e);
+                added.push(new);
+                true
+            }
+        }
+    });
+
+    for old in deleted {
+        let old_output = outputs.remove(old).unwrap_or_else(|| guess_output_path(input, output, old));
+        manifest.remove(&relative_path(input, old));
+        if old_output.is_file() {
+            match remove_file(&old_output) {
+                Ok(_) => log!("Removed {}", old_output.display()),
+                Err(e) => log!(warn: "Failed to remove {}: {}", old_output.display(), e),
+            }
+        }
+    }
+
+    for item in added.into_iter().chain(modified) {
+        let item_output = mirrored_output_path(input, output, item);
+        match convert_fn(item, &item_output) {
+            Ok((item_output, entry)) => {
+                outputs.insert(item.clone(), item_output);
+                // Existing outputs that were kept aren't known to match the input
+                match entry {
+                    Some(entry) => manifest.insert(relative_path(input, item), entry),
+                    None => { manifest.remove(&relative_path(input, item)); }
+                }
+            }
+            Err(e) => {
+                // Failed files are left out of the manifest, so that they are retried next time
+                manifest.remove(&relative_path(input, item));
+                log!(error: "Error converting: {}", e);
+            }
+        }
+    }
+}
+
+/// Takes a snapshot, only logging the failure, such as when a directory disappears while being scanned.
+fn try_snapshot(input: &Path, scan_options: &ScanOptions) -> Option<Snapshot> {
+    match snapshot(input, scan_options) {
+        Ok(snapshot) => Some(snapshot),
+        Err(e) => {
+            log!(warn: "Failed to scan {}, retrying later: {}", input.display(), e);
+            None
+        }
+    }
+}
+
+fn snapshot(input: &Path, scan_options: &ScanOptions) -> Result<Snapshot> {
+    let mut items: Vec<PathBuf> = Vec::new();
+    scan_dir(input, Format::Data.extension(), scan_options, &mut items)?;
+    scan_dir(input, Format::Png.extension(), scan_options, &mut items)?;
+
+    // Files may disappear while being scanned, they will be gone from the next snapshot anyway
+    Ok(items.into_iter().filter_map(|item| Some((item.clone(), file_stamp(&item).ok()?))).collect())
+}
+
+/// Path of the item relative to the directory, as kept in the manifest.
+fn relative_path(dir: &Path, item: &Path) -> PathBuf {
+    diff_paths(item, dir).unwrap_or_else(|| item.to_path_buf())
+}
+
+/// Guesses output path of a file that isn't there anymore, assuming its extension matched its format.
+fn guess_output_path(input: &Path, output: &Path, item: &Path) -> PathBuf {
+    let is_png = item.extension().is_some_and(|e| e.eq_ignore_ascii_case(Format::Png.extension()));
+    let target = if is_png { Format::Data } else { Format::Png };
+    mirrored_output_path(input, output, item).with_extension(target.extension())
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        create_dir_all(parent)?;
+    }
+    rename(from, to)?;
+    Ok(())
+}
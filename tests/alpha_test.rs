@@ -0,0 +1,70 @@
+use celeste_converter::alpha::{premultiply, unpremultiply};
+use rstest::rstest;
+
+#[rstest]
+#[case::opaque([200, 100, 0, 255], [200, 100, 0, 255])]
+#[case::half([255, 100, 1, 128], [128, 50, 1, 128])]
+#[case::rounding_down([10, 20, 30, 51], [2, 4, 6, 51])]
+#[case::rounding_up([3, 8, 13, 127], [1, 4, 6, 127])]
+#[case::transparent([255, 255, 255, 0], [0, 0, 0, 0])]
+fn premultiply_rounds_to_nearest(#[case] straight: [u8; 4], #[case] expected: [u8; 4]) {
+    let mut pixel = straight;
+
+    premultiply(&mut pixel);
+
+    assert_eq!(pixel, expected);
+}
+
+#[rstest]
+#[case::opaque([200, 100, 0, 255], [200, 100, 0, 255])]
+#[case::half([128, 50, 1, 128], [255, 100, 2, 128])]
+#[case::rounding([2, 4, 6, 51], [10, 20, 30, 51])]
+#[case::transparent([10, 20, 30, 0], [0, 0, 0, 0])]
+#[case::exceeding_alpha([100, 50, 0, 50], [255, 255, 0, 50])]
+fn unpremultiply_rounds_to_nearest(#[case] premultiplied: [u8; 4], #[case] expected: [u8; 4]) {
+    let mut pixel = premultiplied;
+
+    unpremultiply(&mut pixel);
+
+    assert_eq!(pixel, expected);
+}
+
+#[rstest]
+fn premultiplied_colors_survive_unpremultiplying() {
+    for alpha in 0..=255u8 {
+        for color in 0..=alpha {
+            let mut pixel = [color, color, color, alpha];
+
+            unpremultiply(&mut pixel);
+            premultiply(&mut pixel);
+
+            assert_eq!(pixel, [color, color, color, alpha], "Color {color} with alpha {alpha} changed");
+        }
+    }
+}
+
+#[rstest]
+fn straight_colors_stay_close_after_premultiplying() {
+    for alpha in 1..=255u8 {
+        // Premultiplying loses precision, which is worse the more transparent the pixel is
+        let tolerance = 255.0 / (2.0 * alpha as f64) + 0.5;
+        for color in 0..=255u8 {
+            let mut pixel = [color, color, color, alpha];
+
+            premultiply(&mut pixel);
+            unpremultiply(&mut pixel);
+
+            let diff = pixel[0].abs_diff(color) as f64;
+            assert!(diff <= tolerance, "Color {color} with alpha {alpha} became {}", pixel[0]);
+        }
+    }
+}
+
+#[rstest]
+fn pixels_are_processed_independently() {
+    let mut pixels = [255, 0, 0, 255, 255, 0, 0, 0, 0, 255, 0, 51];
+
+    premultiply(&mut pixels);
+
+    assert_eq!(pixels, [255, 0, 0, 255, 0, 0, 0, 0, 0, 51, 0, 51]);
+}
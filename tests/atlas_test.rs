@@ -0,0 +1,272 @@
+mod common;
+
+use celeste_converter::atlas::{pack, slice, Atlas, AtlasPage, PackOptions, Subtexture};
+use celeste_converter::convert;
+use common::create_empty_dir;
+use image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use rand::random;
+use rstest::rstest;
+use std::fs::{create_dir_all, write, File};
+use std::io::{Cursor, Seek};
+use std::path::PathBuf;
+
+#[rstest]
+fn read_parses_meta() {
+    let meta = vec![
+        0x01, 0x00, 0x00, 0x00, // version
+        0x04, b'a', b'r', b'g', b's', // args
+        0x78, 0x56, 0x34, 0x12, // hash
+        0x01, 0x00, // page count
+        0x05, b'T', b'e', b's', b't', b'0', // page name
+        0x01, 0x00, // subtexture count
+        0x05, b'a', b'\\', b'b', b'/', b'c', // subtexture path
+        0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00, // region
+        0xFF, 0xFF, 0xFE, 0xFF, 0x05, 0x00, 0x06, 0x00, // frame
+    ];
+
+    let atlas = Atlas::read(&mut Cursor::new(meta)).unwrap();
+
+    assert_eq!(atlas.version, 1);
+    assert_eq!(atlas.args, "args");
+    assert_eq!(atlas.hash, 0x12345678);
+    assert_eq!(atlas.pages.len(), 1);
+    assert_eq!(atlas.pages[0].name, "Test0");
+    assert_eq!(atlas.pages[0].subtextures.len(), 1);
+
+    let subtexture = &atlas.pages[0].subtextures[0];
+    assert_eq!(subtexture.path, "a/b/c");
+    assert_eq!((subtexture.x, subtexture.y, subtexture.width, subtexture.height), (1, 2, 3, 4));
+    assert_eq!((subtexture.frame_x, subtexture.frame_y), (-1, -2));
+    assert_eq!((subtexture.frame_width, subtexture.frame_height), (5, 6));
+}
+
+#[rstest]
+fn read_fails_on_truncated_meta() {
+    let meta = vec![0x01, 0x00, 0x00, 0x00, 0x04, b'a', b'r'];
+
+    assert!(Atlas::read(&mut Cursor::new(meta)).is_err());
+}
+
+#[rstest]
+fn write_and_read_matches_original() {
+    let long_path = "x".repeat(300);
+    let atlas = create_atlas(vec![
+        subtexture("characters/player/idle00", (1, 2, 3, 4), (-5, -6, 7, 8)),
+        subtexture(&long_path, (0, 0, 1, 1), (0, 0, 1, 1)),
+    ]);
+
+    let mut meta = Vec::new();
+    atlas.write(&mut meta).unwrap();
+    let read_atlas = Atlas::read(&mut Cursor::new(meta)).unwrap();
+
+    assert_eq!(read_atlas.version, atlas.version);
+    assert_eq!(read_atlas.args, atlas.args);
+    assert_eq!(read_atlas.hash, atlas.hash);
+    assert_eq!(read_atlas.pages.len(), 1);
+    assert_eq!(read_atlas.pages[0].name, "Test0");
+    let paths: Vec<&str> = read_atlas.pages[0].subtextures.iter().map(|s| s.path.as_str()).collect();
+    assert_eq!(paths, ["characters/player/idle00", long_path.as_str()]);
+    assert_eq!(read_atlas.pages[0].subtextures[0].frame_y, -6);
+}
+
+#[rstest]
+fn slice_writes_subtextures_under_logical_paths() {
+    let dir = create_empty_dir();
+    let output = dir.join("output");
+
+    let mut page = RgbaImage::from_pixel(8, 4, Rgba([0, 0, 0, 0]));
+    page.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+    page.put_pixel(1, 1, Rgba([0, 255, 0, 255]));
+    page.put_pixel(4, 0, Rgba([0, 0, 255, 128]));
+    page.put_pixel(7, 3, Rgba([255, 255, 255, 255]));
+    write_page(&page, dir.join("Test0.data"));
+
+    let meta = dir.join("Test.meta");
+    create_atlas(vec![
+        subtexture("characters/player/idle00", (0, 0, 2, 2), (-1, -1, 4, 4)),
+        subtexture("tiles\\grass", (4, 0, 4, 4), (0, 0, 4, 4)),
+    ]).write(&mut File::create(&meta).unwrap()).unwrap();
+
+    slice(&meta, &output).unwrap();
+
+    let idle = load_png_image(output.join("characters/player/idle00.png"));
+    assert_eq!(idle.dimensions(), (4, 4));
+    assert_eq!(idle.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+    assert_eq!(idle.get_pixel(1, 1), Rgba([255, 0, 0, 255]));
+    assert_eq!(idle.get_pixel(2, 2), Rgba([0, 255, 0, 255]));
+    assert_eq!(idle.get_pixel(3, 3), Rgba([0, 0, 0, 0]));
+
+    let grass = load_png_image(output.join("tiles/grass.png"));
+    assert_eq!(grass.dimensions(), (4, 4));
+    assert_eq!(grass.get_pixel(0, 0), Rgba([0, 0, 255, 128]));
+    assert_eq!(grass.get_pixel(3, 3), Rgba([255, 255, 255, 255]));
+}
+
+#[rstest]
+fn slice_fails_on_subtexture_outside_of_page() {
+    let dir = create_empty_dir();
+    write_page(&RgbaImage::new(4, 4), dir.join("Test0.data"));
+
+    let meta = dir.join("Test.meta");
+    create_atlas(vec![subtexture("too/big", (2, 2, 4, 4), (0, 0, 4, 4))])
+        .write(&mut File::create(&meta).unwrap()).unwrap();
+
+    let err = slice(&meta, &dir.join("output")).unwrap_err();
+
+    assert!(err.to_string().contains("lies outside of its atlas page"));
+}
+
+#[rstest]
+fn slice_fails_on_subtexture_path_escaping_output() {
+    let dir = create_empty_dir();
+    write_page(&RgbaImage::new(4, 4), dir.join("Test0.data"));
+
+    let meta = dir.join("Test.meta");
+    create_atlas(vec![subtexture("../escape", (0, 0, 4, 4), (0, 0, 4, 4))])
+        .write(&mut File::create(&meta).unwrap()).unwrap();
+
+    let err = slice(&meta, &dir.join("output")).unwrap_err();
+
+    assert!(err.to_string().contains("Subtexture path is not a plain relative path"));
+    assert!(!dir.join("escape.png").exists());
+}
+
+#[rstest]
+fn pack_trims_sprites_and_records_offsets() {
+    let dir = create_empty_dir();
+    let input = dir.join("input");
+    let meta = dir.join("output/Test.meta");
+
+    let mut sprite = RgbaImage::new(6, 5);
+    sprite.put_pixel(2, 1, Rgba([255, 0, 0, 255]));
+    sprite.put_pixel(3, 3, Rgba([0, 255, 0, 128]));
+    write_png_image(&sprite, input.join("characters/player/idle00.png"));
+
+    pack(&input, &meta, &PackOptions::default()).unwrap();
+
+    let atlas = Atlas::read(&mut File::open(&meta).unwrap()).unwrap();
+    assert_eq!(atlas.pages.len(), 1);
+    assert_eq!(atlas.pages[0].name, "Test0");
+    assert!(dir.join("output/Test0.data").is_file());
+
+    let subtexture = &atlas.pages[0].subtextures[0];
+    assert_eq!(subtexture.path, "characters/player/idle00");
+    assert_eq!((subtexture.width, subtexture.height), (2, 3));
+    assert_eq!((subtexture.frame_x, subtexture.frame_y), (-2, -1));
+    assert_eq!((subtexture.frame_width, subtexture.frame_height), (6, 5));
+}
+
+#[rstest]
+fn pack_and_slice_matches_original() {
+    let dir = create_empty_dir();
+    let input = dir.join("input");
+    let meta = dir.join("packed/Test.meta");
+    let output = dir.join("output");
+
+    let mut sprites = Vec::new();
+    for i in 0..20 {
+        let (width, height) = (1 + random::<u32>() % 16, 1 + random::<u32>() % 16);
+        let sprite = RgbaImage::from_fn(width, height, |_, _| {
+            if random::<bool>() { Rgba([random(), random(), random(), 255]) } else { Rgba([0, 0, 0, 0]) }
+        });
+        let path = format!("sprites/{}/{i:02}", i % 3);
+        write_png_image(&sprite, input.join(format!("{path}.png")));
+        sprites.push((path, sprite));
+    }
+
+    pack(&input, &meta, &PackOptions { max_page_size: 32, padding: 1 }).unwrap();
+    slice(&meta, &output).unwrap();
+
+    for (path, sprite) in sprites {
+        let sliced = load_png_image(output.join(format!("{path}.png")));
+        assert_eq!(sliced.dimensions(), sprite.dimensions(), "Sprite {path} has different dimensions");
+        assert_eq!(sliced.to_rgba8(), sprite, "Sprite {path} differs");
+    }
+}
+
+#[rstest]
+fn pack_splits_sprites_into_multiple_pages() {
+    let dir = create_empty_dir();
+    let input = dir.join("input");
+    let meta = dir.join("output/Test.meta");
+    for i in 0..5 {
+        write_png_image(&RgbaImage::from_pixel(10, 10, Rgba([i, i, i, 255])), input.join(format!("{i}.png")));
+    }
+
+    pack(&input, &meta, &PackOptions { max_page_size: 20, padding: 0 }).unwrap();
+
+    let atlas = Atlas::read(&mut File::open(&meta).unwrap()).unwrap();
+    let page_names: Vec<&str> = atlas.pages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(page_names, ["Test0", "Test1"]);
+    assert_eq!(atlas.pages[0].subtextures.len(), 4);
+    assert_eq!(atlas.pages[1].subtextures.len(), 1);
+    assert!(dir.join("output/Test1.data").is_file());
+}
+
+#[rstest]
+fn pack_fails_on_sprite_bigger_than_page() {
+    let dir = create_empty_dir();
+    let input = dir.join("input");
+    write_png_image(&RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255])), input.join("big.png"));
+
+    let err = pack(&input, &dir.join("Test.meta"), &PackOptions { max_page_size: 8, padding: 1 }).unwrap_err();
+
+    assert!(err.to_string().contains("doesn't fit into a page"));
+}
+
+#[rstest]
+fn pack_fails_on_sprite_with_index_outside_of_palette() {
+    let dir = create_empty_dir();
+    let input = dir.join("input");
+    create_dir_all(&input).unwrap();
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, 2, 1);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_palette(vec![0x10, 0x11, 0x12]);
+    encoder.write_header().unwrap().write_image_data(&[0, 5]).unwrap();
+    write(input.join("bad.png"), png).unwrap();
+
+    let err = pack(&input, &dir.join("Test.meta"), &PackOptions::default()).unwrap_err();
+
+    assert!(err.to_string().contains("palette index 5"));
+}
+
+fn create_atlas(subtextures: Vec<Subtexture>) -> Atlas {
+    Atlas {
+        version: 0,
+        args: String::new(),
+        hash: 0,
+        pages: vec![AtlasPage { name: "Test0".to_string(), subtextures }],
+    }
+}
+
+fn subtexture(path: &str, region: (i16, i16, i16, i16), frame: (i16, i16, i16, i16)) -> Subtexture {
+    Subtexture {
+        path: path.to_string(),
+        x: region.0,
+        y: region.1,
+        width: region.2,
+        height: region.3,
+        frame_x: frame.0,
+        frame_y: frame.1,
+        frame_width: frame.2,
+        frame_height: frame.3,
+    }
+}
+
+fn write_page(page: &RgbaImage, path: PathBuf) {
+    let mut png = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(page.clone()).write_to(&mut png, ImageFormat::Png).unwrap();
+    png.rewind().unwrap();
+
+    convert::png_to_data(&mut png, &mut File::create(path).unwrap()).unwrap();
+}
+
+fn write_png_image(image: &RgbaImage, path: PathBuf) {
+    create_dir_all(path.parent().unwrap()).unwrap();
+    image.save_with_format(path, ImageFormat::Png).unwrap();
+}
+
+fn load_png_image(path: PathBuf) -> DynamicImage {
+    image::ImageReader::open(path).unwrap().decode().unwrap()
+}
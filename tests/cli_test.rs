@@ -0,0 +1,245 @@
+mod common;
+
+use common::create_empty_dir;
+use rstest::rstest;
+use std::fs::{copy, create_dir_all, read_to_string, write};
+use std::process::{Command, Output};
+
+#[rstest]
+fn help_lists_commands() {
+    let output = run(&["--help"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for command in ["convert", "data2png", "png2data", "watch", "verify", "slice", "pack"] {
+        assert!(stdout.contains(command), "Help doesn't mention {command}");
+    }
+}
+
+#[rstest]
+fn command_help_lists_options() {
+    let output = run(&["png2data", "--help"]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--dither"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--alpha-mode"));
+}
+
+#[rstest]
+fn version_prints_package_version() {
+    let output = run(&["--version"]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains(env!("CARGO_PKG_VERSION")));
+}
+
+#[rstest]
+#[case::no_arguments(&[])]
+#[case::unknown_command(&["unknown", "input"])]
+#[case::missing_input(&["png2data"])]
+#[case::unknown_option(&["png2data", "input.png", "--unknown"])]
+#[case::invalid_option_value(&["png2data", "input.png", "--dither", "unknown"])]
+#[case::invalid_color(&["png2data", "input.png", "--palette-fallback", "12345"])]
+#[case::invalid_threads(&["--threads", "many", "verify", "input.data"])]
+#[case::quiet_and_verbose(&["-q", "-v", "verify", "input.data"])]
+#[case::invalid_overwrite_policy(&["png2data", "input.png", "--overwrite", "sometimes"])]
+#[case::invalid_alpha_mode(&["data2png", "input.data", "--alpha-mode", "unknown"])]
+fn invalid_usage_fails(#[case] args: &[&str]) {
+    let output = run(args);
+
+    assert!(!output.status.success());
+}
+
+#[rstest]
+fn convert_file_succeeds() {
+    let dir = create_empty_dir();
+    let input = dir.join("red.png");
+    copy("tests/png/red.png", &input).unwrap();
+
+    let output = run(&["png2data", input.to_str().unwrap(), "--dither", "ordered"]);
+
+    assert!(output.status.success());
+    assert!(dir.join("red.data").is_file());
+}
+
+#[rstest]
+fn convert_with_premultiplied_alpha_succeeds() {
+    let dir = create_empty_dir();
+    let input = dir.join("transparent.png");
+    copy("tests/png/transparent.png", &input).unwrap();
+
+    let output = run(&["convert", input.to_str().unwrap(), "--alpha-mode", "premultiplied"]);
+
+    assert!(output.status.success());
+    assert!(dir.join("transparent.data").is_file());
+}
+
+#[rstest]
+fn convert_non_existing_file_fails() {
+    let dir = create_empty_dir();
+
+    let output = run(&["data2png", dir.join("missing.data").to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[rstest]
+fn convert_dir_with_some_failures_fails() {
+    let input = create_empty_dir();
+    let output_dir = create_empty_dir();
+    copy("tests/data/red.data", input.join("good.data")).unwrap();
+    write(input.join("bad.data"), [1, 2, 3]).unwrap();
+
+    let output = run(&["data2png", input.to_str().unwrap(), output_dir.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output_dir.join("good.png").is_file());
+}
+
+#[rstest]
+fn convert_dir_with_exclude_pattern_skips_files() {
+    let input = create_empty_dir();
+    let output_dir = create_empty_dir();
+    create_dir_all(input.join("Portraits")).unwrap();
+    copy("tests/data/red.data", input.join("red.data")).unwrap();
+    copy("tests/data/red.data", input.join("Portraits/red.data")).unwrap();
+
+    let output = run(&["convert", input.to_str().unwrap(), output_dir.to_str().unwrap(), "--exclude", "**/Portraits/**"]);
+
+    assert!(output.status.success());
+    assert!(output_dir.join("red.png").is_file());
+    assert!(!output_dir.join("Portraits/red.png").exists());
+}
+
+#[rstest]
+fn verify_dir_reports_invalid_files() {
+    let input = create_empty_dir();
+    copy("tests/data/red.data", input.join("good.data")).unwrap();
+    write(input.join("bad.data"), [1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 1, 2, 3]).unwrap();
+
+    let output = run(&["verify", input.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("byte 9, pixel 0: RLE count has value of 0"));
+    assert!(stderr.contains("1/2 verified successfully"));
+}
+
+#[rstest]
+fn verify_valid_file_succeeds() {
+    let output = run(&["verify", "tests/data/multi-color.data"]);
+
+    assert!(output.status.success());
+}
+
+#[rstest]
+fn verify_uses_the_same_limits_as_data2png() {
+    let dir = create_empty_dir();
+    let input = dir.join("wide.data");
+    // Valid image of 16385x1 pixels, which is wider than the default limit
+    let mut data = vec![0x01, 0x40, 0, 0, 1, 0, 0, 0, 0];
+    data.extend([255, 1, 2, 3].repeat(64));
+    data.extend([65, 1, 2, 3]);
+    write(&input, data).unwrap();
+
+    let verified = run(&["verify", input.to_str().unwrap()]);
+    let converted = run(&["data2png", input.to_str().unwrap()]);
+    let verified_with_raised_limit = run(&["verify", input.to_str().unwrap(), "--max-dimension", "16385"]);
+
+    assert_eq!(verified.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&verified.stderr).contains("image of 16385x1 exceeds the size limits"));
+    assert_eq!(converted.status.code(), Some(1));
+    assert!(verified_with_raised_limit.status.success());
+}
+
+#[rstest]
+fn logs_go_to_stderr() {
+    let output = run(&["verify", "tests/data/multi-color.data"]);
+
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("OK"));
+}
+
+#[rstest]
+fn quiet_only_logs_errors() {
+    let dir = create_empty_dir();
+
+    let valid = run(&["--quiet", "verify", "tests/data/multi-color.data"]);
+    let invalid = run(&["verify", "-q", dir.join("missing.data").to_str().unwrap()]);
+
+    assert!(valid.stderr.is_empty());
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("ERROR: Input path can't be recognized"));
+}
+
+#[rstest]
+fn verbose_logs_details() {
+    let dir = create_empty_dir();
+    copy("tests/data/red.data", dir.join("red.data")).unwrap();
+
+    let output = run(&["data2png", "-v", dir.join("red.data").to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("DEBUG: DATA image parameters: 32x32"));
+}
+
+#[rstest]
+fn json_log_format_writes_object_per_line() {
+    let output = run(&["--log-format", "json", "verify", "tests/data/multi-color.data"]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.lines().count() > 0);
+    for line in stderr.lines() {
+        assert!(line.starts_with(r#"{"level":"info","thread":"main","message":""#), "Unexpected line {line}");
+        assert!(line.ends_with(r#""}"#), "Unexpected line {line}");
+    }
+}
+
+#[rstest]
+fn progress_is_shown_when_forced() {
+    let input = create_empty_dir();
+    let output_dir = create_empty_dir();
+    copy("tests/data/red.data", input.join("red.data")).unwrap();
+
+    let shown = run(&["--progress", "always", "convert", input.to_str().unwrap(), output_dir.to_str().unwrap()]);
+    let hidden = run(&["convert", input.to_str().unwrap(), output_dir.to_str().unwrap(), "--force"]);
+
+    assert!(String::from_utf8_lossy(&shown.stderr).contains("1/1 files"));
+    assert!(!String::from_utf8_lossy(&hidden.stderr).contains("1/1 files"));
+}
+
+#[rstest]
+fn convert_writes_report_even_when_failing() {
+    let input = create_empty_dir();
+    let output_dir = create_empty_dir();
+    copy("tests/data/red.data", input.join("good.data")).unwrap();
+    write(input.join("bad.data"), [1, 2, 3]).unwrap();
+    let report = output_dir.join("report.csv");
+
+    let output = run(&["convert", input.to_str().unwrap(), output_dir.to_str().unwrap(), "--report", report.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let report = read_to_string(report).unwrap();
+    assert_eq!(report.lines().count(), 3);
+    assert!(report.contains(",failed,"));
+    assert!(report.contains(",converted,"));
+}
+
+#[rstest]
+fn single_thread_converts_on_main_thread() {
+    let input = create_empty_dir();
+    let output_dir = create_empty_dir();
+    copy("tests/data/red.data", input.join("good.data")).unwrap();
+    write(input.join("bad.data"), [1, 2, 3]).unwrap();
+
+    let output = run(&["--threads", "1", "convert", input.to_str().unwrap(), output_dir.to_str().unwrap()]);
+
+    assert!(output_dir.join("good.png").is_file());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[main] ERROR: Error converting"));
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_celeste-converter"))
+        .args(args)
+        .output()
+        .unwrap()
+}
@@ -0,0 +1,10 @@
+use rand::random;
+use std::env::temp_dir;
+use std::fs::create_dir_all;
+use std::path::PathBuf;
+
+pub fn create_empty_dir() -> PathBuf {
+    let path = temp_dir().join(random::<u64>().to_string());
+    create_dir_all(&path).unwrap();
+    path
+}
@@ -1,4 +1,9 @@
+use celeste_converter::alpha::{premultiply, AlphaMode};
 use celeste_converter::convert;
+use celeste_converter::convert::{DataToPngOptions, Format, PngToDataOptions};
+use celeste_converter::data::{DataError, DataLimits};
+use celeste_converter::dither::Dither;
+use celeste_converter::error::Error;
 use image::{DynamicImage, GenericImageView};
 use image::ImageFormat;
 use rstest::rstest;
@@ -57,14 +62,428 @@ fn data_to_png_twice_matches_original(#[case] case: &str, #[case] sixteen_bit: b
 }
 
 #[apply(all_image_cases)]
-fn png_to_data_and_back_matches_original(#[case] case: &str, #[case] sixteen_bit: bool) {
+fn png_to_data_and_back_matches_original(#[case] case: &str, #[case] _sixteen_bit: bool) {
     let original_png_bytes = load_png_bytes(case);
 
     let converted_data_bytes = png_bytes_to_data_bytes(&original_png_bytes);
     let converted_png_image = data_bytes_to_png_image(&converted_data_bytes);
 
+    // 16-bit samples are rounded to the nearest 8-bit value, so no tolerance is needed
     let original_png_image = load_png_image(case);
-    assert_png_image_eq(&converted_png_image, &original_png_image, sixteen_bit);
+    assert_png_image_eq(&converted_png_image, &original_png_image, false);
+}
+
+#[template]
+#[rstest]
+#[case::white("white")]
+#[case::red("red")]
+#[case::green("green")]
+#[case::blue("blue")]
+#[case::cyan("cyan")]
+#[case::magenta("magenta")]
+#[case::yellow("yellow")]
+#[case::black("black")]
+#[case::transparent("transparent")]
+#[case::multi_color("multi-color")]
+#[case::big_test_no_background("big-test-no-background")]
+#[case::ffmpeg_rgb24("ffmpeg/rgb24")]
+#[case::ffmpeg_rgba("ffmpeg/rgba")]
+#[case::ffmpeg_pal8("ffmpeg/pal8")]
+#[case::ffmpeg_gray("ffmpeg/gray")]
+#[case::ffmpeg_ya8("ffmpeg/ya8")]
+#[case::ffmpeg_monob("ffmpeg/monob")]
+#[case::ffmpeg_monob_prime_dimensions("ffmpeg/monob-prime-dimensions")]
+fn eight_bit_image_cases(#[case] case: &str) {}
+
+#[apply(eight_bit_image_cases)]
+fn png_to_data_is_no_larger_than_original(#[case] case: &str) {
+    let original_data_bytes = load_data_bytes(case);
+    let original_png_bytes = load_png_bytes(case);
+
+    let converted_data_bytes = png_bytes_to_data_bytes(&original_png_bytes);
+
+    assert!(
+        converted_data_bytes.len() <= original_data_bytes.len(),
+        "Converted DATA is larger than original: {} > {}", converted_data_bytes.len(), original_data_bytes.len()
+    );
+}
+
+#[rstest]
+#[case::white("white")]
+#[case::red("red")]
+#[case::black("black")]
+#[case::transparent("transparent")]
+#[case::multi_color("multi-color")]
+#[case::big_test_no_background("big-test-no-background")]
+fn png_to_data_matches_original_bytes(#[case] case: &str) {
+    let original_data_bytes = load_data_bytes(case);
+    let original_png_bytes = load_png_bytes(case);
+
+    let converted_data_bytes = png_bytes_to_data_bytes(&original_png_bytes);
+
+    assert!(converted_data_bytes == original_data_bytes, "Converted DATA differs from original");
+}
+
+#[rstest]
+#[case::rgb(image::Rgba([10, 20, 30, 255]), false, 1 + 3)]
+#[case::rgba(image::Rgba([10, 20, 30, 128]), true, 1 + 4)]
+#[case::transparent(image::Rgba([10, 20, 30, 0]), true, 1 + 1)]
+fn png_to_data_merges_runs_across_chunks(#[case] color: image::Rgba<u8>, #[case] has_alpha: bool, #[case] sample_size: usize) {
+    // Large enough to be split into multiple chunks during conversion
+    let (width, height) = (300, 500);
+    let image = image::RgbaImage::from_pixel(width, height, color);
+    let png = if has_alpha {
+        DynamicImage::ImageRgba8(image)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(image).to_rgb8())
+    };
+
+    let converted_data_bytes = png_image_to_data_bytes(&png);
+
+    let run_count = ((width * height) as usize).div_ceil(255);
+    assert_eq!(converted_data_bytes.len(), 9 + run_count * sample_size);
+}
+
+#[rstest]
+fn png_to_data_merges_transparent_pixels_of_different_colors() {
+    let mut image = image::RgbaImage::new(4, 1);
+    image.put_pixel(0, 0, image::Rgba([10, 20, 30, 0]));
+    image.put_pixel(1, 0, image::Rgba([40, 50, 60, 0]));
+    image.put_pixel(2, 0, image::Rgba([70, 80, 90, 0]));
+    image.put_pixel(3, 0, image::Rgba([0, 0, 0, 0]));
+
+    let converted_data_bytes = png_image_to_data_bytes(&DynamicImage::ImageRgba8(image));
+
+    assert_eq!(converted_data_bytes, [4, 0, 0, 0, 1, 0, 0, 0, 1, 4, 0]);
+}
+
+#[rstest]
+#[case::pal8("trns/pal8")]
+#[case::pal2("trns/pal2")]
+#[case::gray("trns/gray")]
+#[case::gray4("trns/gray4")]
+#[case::rgb24("trns/rgb24")]
+#[case::rgb48("trns/rgb48")]
+fn png_with_trns_to_data_and_back_matches_original(#[case] case: &str) {
+    let original_png_bytes = load_png_bytes(case);
+
+    let converted_data_bytes = png_bytes_to_data_bytes(&original_png_bytes);
+    let converted_png_image = data_bytes_to_png_image(&converted_data_bytes);
+
+    // DATA doesn't store color of fully transparent pixels
+    let mut original_png_image = load_png_image(case).to_rgba8();
+    for pixel in original_png_image.pixels_mut() {
+        if pixel[3] == 0 {
+            *pixel = image::Rgba([0, 0, 0, 0]);
+        }
+    }
+
+    assert_eq!(converted_data_bytes[8], 1, "Converted DATA doesn't have alpha");
+    assert_png_image_eq(&converted_png_image, &DynamicImage::ImageRgba8(original_png_image), false);
+}
+
+#[rstest]
+#[case::ordered(Dither::Ordered)]
+#[case::floyd_steinberg(Dither::FloydSteinberg)]
+fn png_to_data_with_dither_stays_close_to_original(#[case] dither: Dither) {
+    let original_png_bytes = load_png_bytes("ffmpeg/rgba64be");
+
+    let mut converted_data_bytes = Vec::new();
+    let options = PngToDataOptions { dither, ..Default::default() };
+    convert::png_to_data_with_options(&mut Cursor::new(original_png_bytes), &mut converted_data_bytes, &options).unwrap();
+    let converted_png_image = data_bytes_to_png_image(&converted_data_bytes);
+
+    let original_png_image = load_png_image("ffmpeg/rgba64be");
+    assert_png_image_eq(&converted_png_image, &original_png_image, true);
+}
+
+#[rstest]
+#[case::buffered(false)]
+#[case::streaming(true)]
+fn png_to_data_premultiplies_gradient(#[case] streaming: bool) {
+    let gradient = gradient_image();
+    let options = PngToDataOptions { alpha_mode: AlphaMode::Premultiplied, streaming, ..Default::default() };
+
+    let mut png = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(gradient.clone()).write_to(&mut png, ImageFormat::Png).unwrap();
+    png.rewind().unwrap();
+    let mut converted_data_bytes = Vec::new();
+    convert::png_to_data_with_options(&mut png, &mut converted_data_bytes, &options).unwrap();
+
+    let mut expected = gradient.into_raw();
+    premultiply(&mut expected);
+    let straight_png_image = data_bytes_to_png_image(&converted_data_bytes);
+    assert_eq!(straight_png_image.to_rgba8().into_raw(), expected);
+}
+
+#[rstest]
+#[case::buffered(false)]
+#[case::streaming(true)]
+fn data_to_png_unpremultiplies_gradient(#[case] streaming: bool) {
+    let gradient = gradient_image();
+    let mut premultiplied = gradient.clone();
+    premultiply(&mut premultiplied);
+    let data = png_image_to_data_bytes(&DynamicImage::ImageRgba8(premultiplied));
+    let options = DataToPngOptions { alpha_mode: AlphaMode::Premultiplied, streaming, ..Default::default() };
+
+    let mut converted_png_bytes = Cursor::new(Vec::new());
+    convert::data_to_png_with_options(&mut Cursor::new(data), &mut converted_png_bytes, &options).unwrap();
+    converted_png_bytes.rewind().unwrap();
+    let converted_png_image = image::ImageReader::with_format(converted_png_bytes, ImageFormat::Png).decode().unwrap();
+
+    // Color of transparent pixels is lost, the rest only loses precision of the more transparent pixels
+    for (actual, expected) in converted_png_image.to_rgba8().pixels().zip(gradient.pixels()) {
+        let alpha = expected[3];
+        assert_eq!(actual[3], alpha);
+        for i in 0..3 {
+            let tolerance = if alpha == 0 { 255.0 } else { 255.0 / (2.0 * alpha as f64) + 0.5 };
+            assert!(actual[i].abs_diff(expected[i]) as f64 <= tolerance, "{:?} differs from {:?}", actual, expected);
+        }
+    }
+}
+
+#[rstest]
+fn premultiplied_round_trip_keeps_data() {
+    let mut premultiplied = gradient_image();
+    premultiply(&mut premultiplied);
+    let data = png_image_to_data_bytes(&DynamicImage::ImageRgba8(premultiplied));
+    let data_options = DataToPngOptions { alpha_mode: AlphaMode::Premultiplied, ..Default::default() };
+    let png_options = PngToDataOptions { alpha_mode: AlphaMode::Premultiplied, ..Default::default() };
+
+    let mut png = Cursor::new(Vec::new());
+    convert::data_to_png_with_options(&mut Cursor::new(&data), &mut png, &data_options).unwrap();
+    png.rewind().unwrap();
+    let mut converted_data_bytes = Vec::new();
+    convert::png_to_data_with_options(&mut png, &mut converted_data_bytes, &png_options).unwrap();
+
+    assert!(converted_data_bytes == data, "DATA changed after round trip");
+}
+
+#[rstest]
+fn premultiplied_alpha_mode_leaves_opaque_image_alone() {
+    let png = load_png_bytes("multi-color");
+    let options = PngToDataOptions { alpha_mode: AlphaMode::Premultiplied, ..Default::default() };
+
+    let mut converted_data_bytes = Vec::new();
+    convert::png_to_data_with_options(&mut Cursor::new(&png), &mut converted_data_bytes, &options).unwrap();
+
+    assert!(converted_data_bytes == png_bytes_to_data_bytes(&png), "Opaque image changed");
+}
+
+#[apply(eight_bit_image_cases)]
+fn detect_format_recognizes_original_files(#[case] case: &str) {
+    let data_bytes = load_data_bytes(case);
+    let png_bytes = load_png_bytes(case);
+
+    assert_eq!(convert::detect_format(&data_bytes, data_bytes.len() as u64), Some(Format::Data));
+    assert_eq!(convert::detect_format(&png_bytes, png_bytes.len() as u64), Some(Format::Png));
+}
+
+#[rstest]
+#[case::empty(&[], 0)]
+#[case::short_header(&[1, 0, 0, 0, 1, 0, 0, 0], 8)]
+#[case::invalid_alpha_flag(&[1, 0, 0, 0, 1, 0, 0, 0, 2, 1, 0, 0, 0], 13)]
+#[case::too_short_rgb_stream(&[0, 4, 0, 0, 0, 4, 0, 0, 0, 255, 0, 0, 0], 13)]
+#[case::too_long_rgb_stream(&[1, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0], 17)]
+#[case::misaligned_rgb_stream(&[2, 0, 0, 0, 1, 0, 0, 0, 0, 2, 0, 0, 0, 0], 14)]
+#[case::too_long_rgba_stream(&[1, 0, 0, 0, 1, 0, 0, 0, 1, 1, 255, 0, 0, 0, 0], 15)]
+#[case::text(b"Hello, this is not an image", 27)]
+fn detect_format_rejects_implausible_input(#[case] header: &[u8], #[case] len: u64) {
+    assert_eq!(convert::detect_format(header, len), None);
+}
+
+#[rstest]
+fn data_to_png_with_alpha_fails_on_truncated_input() {
+    let data = vec![2, 0, 0, 0, 1, 0, 0, 0, 1, 1, 255, 10, 20, 30, 1, 255, 10];
+
+    let err = convert::data_to_png(&mut Cursor::new(data), &mut Vec::new()).unwrap_err();
+
+    assert!(err.to_string().contains("Unexpected end of DATA"));
+}
+
+#[rstest]
+fn data_to_png_with_alpha_fails_on_runs_exceeding_image_size() {
+    let data = vec![2, 0, 0, 0, 1, 0, 0, 0, 1, 1, 255, 10, 20, 30, 2, 0];
+
+    let err = convert::data_to_png(&mut Cursor::new(data), &mut Vec::new()).unwrap_err();
+
+    assert!(err.to_string().contains("RLE runs exceed the image size"));
+}
+
+#[rstest]
+fn data_to_png_with_alpha_fails_on_trailing_bytes() {
+    let data = vec![1, 0, 0, 0, 1, 0, 0, 0, 1, 1, 0, 7];
+
+    let err = convert::data_to_png(&mut Cursor::new(data), &mut Vec::new()).unwrap_err();
+
+    assert!(err.to_string().contains("Unexpected 1 bytes after the last run"));
+}
+
+#[rstest]
+fn data_to_png_without_alpha_fails_on_truncated_input() {
+    let data = vec![2, 0, 0, 0, 1, 0, 0, 0, 0, 1, 10, 20, 30, 1, 10];
+
+    let err = convert::data_to_png(&mut Cursor::new(data), &mut Vec::new()).unwrap_err();
+
+    assert!(err.to_string().contains("Unexpected end of DATA"));
+}
+
+#[rstest]
+#[case::too_few_pixels(vec![3, 0, 0, 0, 1, 0, 0, 0, 0, 2, 10, 20, 30], "Unexpected end of DATA at byte 13")]
+#[case::too_many_pixels(vec![1, 0, 0, 0, 1, 0, 0, 0, 0, 2, 10, 20, 30], "RLE runs exceed the image size of 1 pixels")]
+fn data_to_png_without_alpha_fails_on_runs_not_matching_image_size(#[case] data: Vec<u8>, #[case] message: &str) {
+    let err = convert::data_to_png(&mut Cursor::new(data), &mut Vec::new()).unwrap_err();
+
+    assert_eq!(err.to_string(), message);
+}
+
+#[rstest]
+#[case::truncated_header(vec![1, 0, 0, 0, 1], DataError::UnexpectedEnd(5))]
+#[case::invalid_alpha_flag(vec![1, 0, 0, 0, 1, 0, 0, 0, 2, 1, 0], DataError::InvalidAlphaFlag(2))]
+#[case::zero_width(vec![0, 0, 0, 0, 1, 0, 0, 0, 0], DataError::ZeroDimension)]
+#[case::overflowing_pixel_count(
+    vec![0, 0, 1, 0, 0, 0, 1, 0, 0, 255, 1, 2, 3],
+    DataError::TooLarge { width: 0x10000, height: 0x10000 },
+)]
+#[case::zero_run_length(vec![1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 1, 0], DataError::ZeroRunLength(9))]
+#[case::trailing_bytes(vec![1, 0, 0, 0, 1, 0, 0, 0, 0, 1, 1, 2, 3, 9], DataError::TrailingBytes { offset: 13, count: 1 })]
+#[case::many_trailing_bytes(
+    vec![1, 0, 0, 0, 1, 0, 0, 0, 0, 1, 1, 2, 3, 9, 9, 9, 9, 9, 9],
+    DataError::TrailingBytes { offset: 13, count: 6 },
+)]
+fn data_to_png_fails_with_data_error(#[case] data: Vec<u8>, #[case] expected: DataError) {
+    let err = convert::data_to_png(&mut Cursor::new(data), &mut Vec::new()).unwrap_err();
+
+    assert_eq!(data_error(err), Some(expected));
+}
+
+#[rstest]
+#[case::max_dimension(DataLimits { max_dimension: 1, max_pixels: 100 })]
+#[case::max_pixels(DataLimits { max_dimension: 100, max_pixels: 3 })]
+fn data_to_png_fails_on_image_exceeding_limits(#[case] limits: DataLimits) {
+    let data = vec![2, 0, 0, 0, 2, 0, 0, 0, 0, 4, 1, 2, 3];
+    let options = DataToPngOptions { limits, ..Default::default() };
+
+    let err = convert::data_to_png_with_options(&mut Cursor::new(data), &mut Vec::new(), &options).unwrap_err();
+
+    assert_eq!(data_error(err), Some(DataError::TooLarge { width: 2, height: 2 }));
+}
+
+#[rstest]
+#[case::red("red")]
+#[case::transparent("transparent")]
+#[case::multi_color("multi-color")]
+#[case::big_test_no_background("big-test-no-background")]
+#[case::ffmpeg_rgb24("ffmpeg/rgb24")]
+#[case::ffmpeg_rgba("ffmpeg/rgba")]
+#[case::ffmpeg_monob_prime_dimensions("ffmpeg/monob-prime-dimensions")]
+fn data_to_png_streaming_matches_buffered(#[case] case: &str) {
+    let data = load_data_bytes(case);
+    let options = DataToPngOptions { streaming: true, ..Default::default() };
+
+    let mut buffered = Vec::new();
+    convert::data_to_png(&mut Cursor::new(&data), &mut buffered).unwrap();
+    let mut streamed = Vec::new();
+    convert::data_to_png_with_options(&mut Cursor::new(&data), &mut streamed, &options).unwrap();
+
+    assert!(streamed == buffered, "Streamed PNG differs from buffered one");
+}
+
+#[rstest]
+#[case::truncated_header(vec![1, 0, 0, 0, 1])]
+#[case::invalid_alpha_flag(vec![1, 0, 0, 0, 1, 0, 0, 0, 2, 1, 0])]
+#[case::zero_run_length(vec![1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 1, 0])]
+#[case::truncated_run(vec![2, 0, 0, 0, 1, 0, 0, 0, 1, 1, 255, 10, 20, 30, 1, 255, 10])]
+#[case::runs_exceeding_image(vec![2, 0, 0, 0, 2, 0, 0, 0, 0, 3, 1, 2, 3, 2, 1, 2, 3])]
+#[case::trailing_bytes(vec![1, 0, 0, 0, 1, 0, 0, 0, 0, 1, 1, 2, 3, 9])]
+fn data_to_png_streaming_fails_like_buffered(#[case] data: Vec<u8>) {
+    let options = DataToPngOptions { streaming: true, ..Default::default() };
+
+    let buffered_err = convert::data_to_png(&mut Cursor::new(&data), &mut Vec::new()).unwrap_err();
+    let streamed_err = convert::data_to_png_with_options(&mut Cursor::new(&data), &mut Vec::new(), &options).unwrap_err();
+
+    assert_eq!(data_error(streamed_err), data_error(buffered_err));
+}
+
+#[rstest]
+#[case::red("red")]
+#[case::transparent("transparent")]
+#[case::multi_color("multi-color")]
+#[case::big_test_no_background("big-test-no-background")]
+#[case::ffmpeg_rgb48be("ffmpeg/rgb48be")]
+#[case::ffmpeg_rgba64be("ffmpeg/rgba64be")]
+#[case::ffmpeg_pal8("ffmpeg/pal8")]
+#[case::ffmpeg_ya16be("ffmpeg/ya16be")]
+#[case::ffmpeg_monob_prime_dimensions("ffmpeg/monob-prime-dimensions")]
+#[case::trns_pal2("trns/pal2")]
+#[case::trns_gray4("trns/gray4")]
+#[case::trns_rgb48("trns/rgb48")]
+#[case::interlaced_rgba("interlaced/rgba")]
+fn png_to_data_streaming_matches_buffered(#[case] case: &str) {
+    let png = load_png_bytes(case);
+    let options = PngToDataOptions { streaming: true, ..Default::default() };
+
+    let buffered = png_bytes_to_data_bytes(&png);
+    let mut streamed = Vec::new();
+    convert::png_to_data_with_options(&mut Cursor::new(&png), &mut streamed, &options).unwrap();
+
+    assert!(streamed == buffered, "Streamed DATA differs from buffered one");
+}
+
+#[rstest]
+fn png_to_data_streaming_fails_on_index_outside_of_palette() {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, 2, 2);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_palette(vec![0x10, 0x11, 0x12, 0x20, 0x21, 0x22]);
+    encoder.write_header().unwrap().write_image_data(&[0, 1, 1, 5]).unwrap();
+    let options = PngToDataOptions { streaming: true, ..Default::default() };
+
+    let err = convert::png_to_data_with_options(&mut Cursor::new(&png), &mut Vec::new(), &options).unwrap_err();
+
+    assert!(err.to_string().contains("palette index 5"));
+}
+
+#[rstest]
+fn data_to_png_fingerprint_ignores_options_not_affecting_output() {
+    let limits = DataLimits { max_pixels: 1, ..Default::default() };
+    let options = DataToPngOptions { limits, streaming: true, ..Default::default() };
+
+    assert_eq!(options.fingerprint(), DataToPngOptions::default().fingerprint());
+}
+
+#[rstest]
+fn data_to_png_fingerprint_changes_with_alpha_mode() {
+    let options = DataToPngOptions { alpha_mode: AlphaMode::Premultiplied, ..Default::default() };
+
+    assert_ne!(options.fingerprint(), DataToPngOptions::default().fingerprint());
+}
+
+#[rstest]
+#[case::none(Dither::None, false)]
+#[case::ordered(Dither::Ordered, false)]
+#[case::floyd_steinberg(Dither::FloydSteinberg, true)]
+fn png_to_data_fingerprint_changes_with_streaming_only_for_error_diffusion(#[case] dither: Dither, #[case] changes: bool) {
+    let buffered = PngToDataOptions { dither, ..Default::default() };
+    let streamed = PngToDataOptions { dither, streaming: true, ..Default::default() };
+
+    assert_eq!(streamed.fingerprint() != buffered.fingerprint(), changes);
+}
+
+#[rstest]
+#[case::dither(PngToDataOptions { dither: Dither::Ordered, ..Default::default() })]
+#[case::palette_fallback(PngToDataOptions { palette_fallback: Some([0, 0, 0, 0]), ..Default::default() })]
+#[case::alpha_mode(PngToDataOptions { alpha_mode: AlphaMode::Premultiplied, ..Default::default() })]
+fn png_to_data_fingerprint_changes_with_options_affecting_output(#[case] options: PngToDataOptions) {
+    assert_ne!(options.fingerprint(), PngToDataOptions::default().fingerprint());
+}
+
+/// Semi-transparent gradient, with colors changing along the width and alpha along the height.
+fn gradient_image() -> image::RgbaImage {
+    const ALPHAS: [u8; 8] = [0, 1, 2, 51, 127, 128, 254, 255];
+    image::RgbaImage::from_fn(256, ALPHAS.len() as u32, |x, y| {
+        image::Rgba([x as u8, 255 - x as u8, (x * 7) as u8, ALPHAS[y as usize]])
+    })
 }
 
 fn load_png_image(image: &str) -> DynamicImage {
@@ -138,3 +557,10 @@ fn assert_png_image_eq(actual: &DynamicImage, expected: &DynamicImage, sixteen_b
         }
     }
 }
+
+fn data_error(err: Error) -> Option<DataError> {
+    match err {
+        Error::Data(e) => Some(e),
+        _ => None,
+    }
+}
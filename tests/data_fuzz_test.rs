@@ -0,0 +1,91 @@
+use celeste_converter::data::{DataImage, DataLimits, DataRows};
+use celeste_converter::error::Result;
+use celeste_converter::verify::verify;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rstest::rstest;
+use std::io::Cursor;
+
+const ITERATIONS: usize = 2000;
+
+#[rstest]
+#[case::red("red")]
+#[case::transparent("transparent")]
+#[case::multi_color("multi-color")]
+#[case::ffmpeg_rgb24("ffmpeg/rgb24")]
+#[case::ffmpeg_rgba("ffmpeg/rgba")]
+fn read_mutated_data_agrees_with_verify(#[case] case: &str) {
+    let original = std::fs::read(format!("tests/data/{case}.data")).unwrap();
+    let mut rng = StdRng::seed_from_u64(0x0CE1E57E);
+
+    for _ in 0..ITERATIONS {
+        let data = mutate(&original, &mut rng);
+        check_read(&data);
+    }
+}
+
+#[rstest]
+fn read_random_data_agrees_with_verify() {
+    let mut rng = StdRng::seed_from_u64(0xDA7A);
+
+    for _ in 0..ITERATIONS {
+        // Keep dimensions small most of the time, so that the stream has a chance to be valid
+        let width: u32 = if rng.gen_bool(0.9) { rng.gen_range(0..8) } else { rng.gen_range(0..=u32::MAX) };
+        let height: u32 = if rng.gen_bool(0.9) { rng.gen_range(0..8) } else { rng.gen_range(0..=u32::MAX) };
+        let mut data = Vec::new();
+        data.extend(width.to_le_bytes());
+        data.extend(height.to_le_bytes());
+        data.push(rng.gen_range(0..3));
+        data.extend((0..rng.gen_range(0..64)).map(|_| rng.gen_range(0..4) as u8));
+
+        check_read(&data);
+    }
+}
+
+/// Applies a few random corruptions: byte changes, truncation or insertion.
+fn mutate(data: &[u8], rng: &mut StdRng) -> Vec<u8> {
+    let mut data = data.to_vec();
+    for _ in 0..rng.gen_range(1..4) {
+        match rng.gen_range(0..3) {
+            0 if !data.is_empty() => {
+                let i = rng.gen_range(0..data.len());
+                data[i] = rng.gen_range(0..=u8::MAX);
+            }
+            1 => data.truncate(rng.gen_range(0..=data.len())),
+            _ => {
+                let i = rng.gen_range(0..=data.len());
+                data.insert(i, rng.gen_range(0..=u8::MAX));
+            }
+        }
+    }
+    data
+}
+
+/// Decoding must never panic, and must fail exactly when verification finds issues.
+/// Decoding row by row must produce the same pixels.
+fn check_read(data: &[u8]) {
+    let verification = verify(&mut Cursor::new(data)).unwrap();
+
+    match DataImage::read(&mut Cursor::new(data)) {
+        Ok(image) => {
+            assert!(verification.is_valid(), "Decoded DATA with issue: {}", verification.issues[0]);
+            let pixel_size = if image.has_alpha { 4 } else { 3 };
+            assert_eq!(image.pixels.len(), (image.width * image.height) as usize * pixel_size);
+            assert_eq!(read_rows(data).unwrap(), image.pixels);
+        }
+        Err(e) => {
+            assert!(!verification.is_valid(), "Failed to decode valid DATA: {}", e);
+            assert!(read_rows(data).is_err(), "Decoded invalid DATA row by row");
+        }
+    }
+}
+
+fn read_rows(data: &[u8]) -> Result<Vec<u8>> {
+    let mut rows = DataRows::new(Cursor::new(data), &DataLimits::default())?;
+    let mut pixels = vec![0; rows.row_len() * rows.height as usize];
+    for row in pixels.chunks_exact_mut(rows.row_len()) {
+        rows.read_row(row)?;
+    }
+    rows.finish()?;
+    Ok(pixels)
+}
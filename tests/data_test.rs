@@ -0,0 +1,117 @@
+use celeste_converter::data::{DataImage, DataWriter};
+use celeste_converter::png::Png;
+use rstest::rstest;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+#[rstest]
+#[case::red("red", false)]
+#[case::transparent("transparent", true)]
+#[case::multi_color("multi-color", true)]
+#[case::big_test_no_background("big-test-no-background", true)]
+#[case::ffmpeg_rgba("ffmpeg/rgba", true)]
+fn read_matches_original_png(#[case] case: &str, #[case] has_alpha: bool) {
+    let mut input = BufReader::new(File::open(format!("tests/data/{case}.data")).unwrap());
+
+    let image = DataImage::read(&mut input).unwrap();
+
+    let png = image::ImageReader::open(format!("tests/png/{case}.png")).unwrap().decode().unwrap();
+    assert_eq!((image.width, image.height), (png.width(), png.height()));
+    assert_eq!(image.has_alpha, has_alpha);
+    assert!(image.to_rgba() == png.to_rgba8().into_raw(), "Images differ");
+}
+
+#[rstest]
+#[case::red("red")]
+#[case::transparent("transparent")]
+#[case::multi_color("multi-color")]
+#[case::big_test_no_background("big-test-no-background")]
+fn from_png_and_write_matches_original_data(#[case] case: &str) {
+    let png = Png::load(&mut BufReader::new(File::open(format!("tests/png/{case}.png")).unwrap())).unwrap();
+
+    let mut output = Vec::new();
+    DataImage::from_png(&png).write(&mut output).unwrap();
+
+    let original = std::fs::read(format!("tests/data/{case}.data")).unwrap();
+    assert!(output == original, "DATA differs from original");
+}
+
+#[rstest]
+fn write_and_read_matches_original() {
+    let pixels: Vec<u8> = (0..5 * 7).flat_map(|i| [i as u8, 10, 20, if i % 3 == 0 { 0 } else { 128 }]).collect();
+    let image = DataImage { width: 5, height: 7, has_alpha: true, pixels };
+
+    let mut data = Vec::new();
+    image.write(&mut data).unwrap();
+    let read_image = DataImage::read(&mut Cursor::new(data)).unwrap();
+
+    assert_eq!((read_image.width, read_image.height, read_image.has_alpha), (5, 7, true));
+    assert_eq!(read_image.pixels, image.to_rgba().chunks_exact(4).flat_map(|p| {
+        // Color of fully transparent pixels isn't stored
+        if p[3] == 0 { [0, 0, 0, 0] } else { [p[0], p[1], p[2], p[3]] }
+    }).collect::<Vec<u8>>());
+}
+
+#[rstest]
+fn write_fails_on_wrong_pixel_count() {
+    let image = DataImage { width: 2, height: 2, has_alpha: false, pixels: vec![0; 4 * 4] };
+
+    let err = image.write(&mut Vec::new()).unwrap_err();
+
+    assert!(err.to_string().contains("must have 12 bytes of pixel data"));
+}
+
+#[rstest]
+fn from_rgba_drops_alpha_of_opaque_pixels() {
+    let image = DataImage::from_rgba(2, 1, vec![1, 2, 3, 255, 4, 5, 6, 255]).unwrap();
+
+    assert!(!image.has_alpha);
+    assert_eq!(image.pixels, [1, 2, 3, 4, 5, 6]);
+    assert_eq!(image.to_rgba(), [1, 2, 3, 255, 4, 5, 6, 255]);
+}
+
+#[rstest]
+fn from_rgba_keeps_alpha_of_translucent_pixels() {
+    let image = DataImage::from_rgba(2, 1, vec![1, 2, 3, 255, 4, 5, 6, 7]).unwrap();
+
+    assert!(image.has_alpha);
+    assert_eq!(image.pixels, [1, 2, 3, 255, 4, 5, 6, 7]);
+}
+
+#[rstest]
+fn from_rgba_fails_on_wrong_pixel_count() {
+    let err = DataImage::from_rgba(2, 2, vec![0; 3]).err().unwrap();
+
+    assert!(err.to_string().contains("must have 16 bytes of pixel data"));
+}
+
+#[rstest]
+#[case::single_piece(35)]
+#[case::rows(5)]
+#[case::uneven_pieces(4)]
+fn writer_with_pieces_matches_write(#[case] piece_len: usize) {
+    let pixels: Vec<u8> = (0..5 * 7).flat_map(|i| [(i / 6) as u8, 10, 20, if i % 9 == 0 { 0 } else { 255 }]).collect();
+    let image = DataImage { width: 5, height: 7, has_alpha: true, pixels };
+    let mut expected = Vec::new();
+    image.write(&mut expected).unwrap();
+
+    let mut output = Vec::new();
+    let mut writer = DataWriter::new(&mut output, 5, 7, true).unwrap();
+    for piece in image.pixels.chunks(piece_len * 4) {
+        writer.write_pixels(piece).unwrap();
+    }
+    writer.finish().unwrap();
+
+    assert_eq!(output, expected);
+}
+
+#[rstest]
+#[case::missing_pixels(3, "missing 1 pixels")]
+#[case::extra_pixels(5, "can't have")]
+fn writer_fails_on_wrong_pixel_count(#[case] pixel_count: usize, #[case] message: &str) {
+    let mut writer = DataWriter::new(Vec::new(), 2, 2, false).unwrap();
+
+    let result = writer.write_pixels(&vec![7; pixel_count * 3]).and_then(|_| writer.finish());
+
+    assert!(result.unwrap_err().to_string().contains(message));
+}
@@ -0,0 +1,67 @@
+use celeste_converter::dither::{dither_sixteen_bit, Dither};
+use rstest::rstest;
+use rstest_reuse::{apply, template};
+
+#[template]
+#[rstest]
+#[case::none(Dither::None)]
+#[case::ordered(Dither::Ordered)]
+#[case::floyd_steinberg(Dither::FloydSteinberg)]
+fn all_dither_cases(#[case] dither: Dither) {}
+
+#[apply(all_dither_cases)]
+fn dither_keeps_extreme_values(#[case] dither: Dither) {
+    let data = [0x00, 0x00, 0xFF, 0xFF].repeat(32);
+    let dithered = dither_sixteen_bit(&data, 2, 4, 0, dither);
+    assert_eq!(dithered, [0, 255].repeat(32));
+}
+
+#[apply(all_dither_cases)]
+fn dither_keeps_exact_values(#[case] dither: Dither) {
+    // 0x6464 corresponds exactly to 8-bit value of 100
+    let data = [0x64, 0x64].repeat(64);
+    let dithered = dither_sixteen_bit(&data, 1, 8, 0, dither);
+    assert_eq!(dithered, [100].repeat(64));
+}
+
+#[rstest]
+#[case::ordered(Dither::Ordered)]
+#[case::floyd_steinberg(Dither::FloydSteinberg)]
+fn dither_preserves_average_value(#[case] dither: Dither) {
+    // 0x80C0 lies between 8-bit values of 128 and 129, a quarter of the way up
+    let data = [0x80, 0xC0].repeat(16 * 16);
+    let dithered = dither_sixteen_bit(&data, 1, 16, 0, dither);
+
+    let average = dithered.iter().map(|&v| v as f64).sum::<f64>() / dithered.len() as f64;
+    assert!(dithered.iter().all(|&v| v == 128 || v == 129), "Dithered values are too far off");
+    assert!((average - 128.25).abs() < 0.05, "Average value of {average} is too far off");
+}
+
+#[rstest]
+fn none_rounds_to_nearest_value() {
+    let data = [0x00, 0x80, 0x00, 0x81];
+    let dithered = dither_sixteen_bit(&data, 1, 2, 0, Dither::None);
+    assert_eq!(dithered, [0, 1]);
+}
+
+#[rstest]
+fn ordered_depends_only_on_pixel_position() {
+    let data: Vec<u8> = (0..7 * 5 * 3).flat_map(|i: u32| ((i * 997) as u16).to_be_bytes()).collect();
+    let whole = dither_sixteen_bit(&data, 3, 7, 0, Dither::Ordered);
+
+    // Split at pixel 11, which is in the middle of the second row
+    let (first, second) = data.split_at(11 * 3 * 2);
+    let mut chunked = dither_sixteen_bit(first, 3, 7, 0, Dither::Ordered);
+    chunked.extend(dither_sixteen_bit(second, 3, 7, 11, Dither::Ordered));
+
+    assert_eq!(chunked, whole);
+}
+
+#[rstest]
+fn floyd_steinberg_chunk_can_start_mid_row() {
+    let data = [0x80, 0xC0].repeat(10);
+    let dithered = dither_sixteen_bit(&data, 1, 4, 3, Dither::FloydSteinberg);
+
+    assert_eq!(dithered.len(), 10);
+    assert!(dithered.iter().all(|&v| v == 128 || v == 129));
+}
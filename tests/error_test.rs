@@ -0,0 +1,121 @@
+mod common;
+
+use celeste_converter::atlas::{slice, Atlas, AtlasError, AtlasPage};
+use celeste_converter::convert;
+use celeste_converter::data::DataError;
+use celeste_converter::error::Error;
+use celeste_converter::file::{convert_with_options, data_to_png, FileOptions, OverwritePolicy};
+use celeste_converter::png::{Png, PngError};
+use common::create_empty_dir;
+use png::BitDepth::Eight;
+use png::ColorType::Indexed;
+use rstest::rstest;
+use std::error::Error as _;
+use std::fs::{write, File};
+use std::io::Cursor;
+use std::path::PathBuf;
+
+#[rstest]
+fn zero_run_length_has_byte_offset() {
+    let data = vec![1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 1, 2, 3];
+
+    let err = convert::data_to_png(&mut Cursor::new(data), &mut Vec::new()).unwrap_err();
+
+    assert!(matches!(err, Error::Data(DataError::ZeroRunLength(9))));
+}
+
+#[rstest]
+fn truncated_stream_has_byte_offset() {
+    let data = vec![2, 0, 0, 0, 1, 0, 0, 0, 0, 1, 1, 2, 3, 1];
+
+    let err = convert::data_to_png(&mut Cursor::new(data), &mut Vec::new()).unwrap_err();
+
+    assert!(matches!(err, Error::Data(DataError::UnexpectedEnd(14))));
+}
+
+#[rstest]
+fn unsupported_png_keeps_decoding_error() {
+    let err = convert::png_to_data(&mut Cursor::new(b"not a PNG"), &mut Vec::new()).unwrap_err();
+
+    assert!(matches!(err, Error::Png(PngError::Decoding(_))));
+    assert!(err.source().is_some());
+}
+
+#[rstest]
+fn palette_index_out_of_range_has_pixel_index() {
+    let png = Png::new(4, 1, Indexed, Eight, vec![0, 0, 3, 0], Some(vec![1, 2, 3])).unwrap();
+
+    let err = png.check_palette().unwrap_err();
+
+    assert!(matches!(err, Error::Png(PngError::PaletteIndexOutOfRange { pixel: 2, index: 3, entries: 1 })));
+}
+
+#[rstest]
+fn pixel_data_length_has_dimensions() {
+    let err = Png::new(2, 2, Indexed, Eight, vec![0, 0], Some(vec![1, 2, 3])).err().unwrap();
+
+    assert!(matches!(err, Error::PixelDataLength { width: 2, height: 2, expected: 4, actual: 2 }));
+}
+
+#[rstest]
+fn existing_output_has_path() {
+    let dir = create_empty_dir();
+    let input = dir.join("input.from");
+    let output = dir.join("output.to");
+    write(&input, "").unwrap();
+    write(&output, "").unwrap();
+    let options = FileOptions { overwrite: OverwritePolicy::FailIfExists, ..Default::default() };
+
+    let err = convert_with_options(&input, Some(&output), "from", "to", &options, |_, _| Ok(())).unwrap_err();
+
+    assert!(matches!(&err, Error::OutputExists(path) if *path == output));
+    assert!(err.to_string().contains("Output file already exists"));
+}
+
+#[rstest]
+fn same_file_has_path() {
+    let dir = create_empty_dir();
+    let input = dir.join("input.from");
+    write(&input, "").unwrap();
+
+    let err = convert_with_options(&input, Some(&input), "from", "to", &FileOptions::default(), |_, _| Ok(())).unwrap_err();
+
+    assert!(matches!(&err, Error::SameFile(path) if *path == input));
+}
+
+#[rstest]
+fn missing_input_has_path() {
+    let input = create_empty_dir().join("missing.data");
+
+    let err = data_to_png(input.clone(), None, &Default::default(), &FileOptions::default()).unwrap_err();
+
+    assert!(matches!(&err, Error::InputNotFound(path) if *path == input));
+}
+
+#[rstest]
+fn atlas_error_has_type() {
+    let meta = [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF];
+
+    let err = Atlas::read(&mut Cursor::new(meta)).err().unwrap();
+
+    assert!(matches!(err, Error::Atlas(AtlasError::NegativeCount(-1))));
+}
+
+#[rstest]
+fn missing_atlas_page_has_path() {
+    let dir = create_empty_dir();
+    let atlas = Atlas { version: 0, args: String::new(), hash: 0, pages: vec![AtlasPage { name: "Test0".into(), subtextures: vec![] }] };
+    atlas.write(&mut File::create(dir.join("Test.meta")).unwrap()).unwrap();
+
+    let err = slice(&dir.join("Test.meta"), &dir.join("output")).unwrap_err();
+
+    assert!(matches!(&err, Error::OpenInput { path, .. } if *path == dir.join("Test0.data")));
+}
+
+#[rstest]
+fn innermost_skips_input_context() {
+    let err = Error::Convert { input: PathBuf::from("a.data"), source: Box::new(DataError::ZeroDimension.into()) };
+
+    assert!(matches!(err.innermost(), Error::Data(DataError::ZeroDimension)));
+    assert_eq!(err.to_string(), "a.data: Image has zero width or height");
+}
@@ -1,9 +1,20 @@
-use celeste_converter::file::convert;
-use rand::random;
+mod common;
+
+use celeste_converter::error::{Error, Result};
+use celeste_converter::convert::{DataToPngOptions, PngToDataOptions};
+use celeste_converter::file::{
+    convert, convert_auto, convert_with_fingerprint, convert_with_options, FileOptions, OverwritePolicy, ScanOptions,
+};
+use celeste_converter::manifest::{Manifest, MANIFEST_FILE_NAME};
+use celeste_converter::progress::Observer;
+use common::create_empty_dir;
 use rstest::rstest;
-use std::env::temp_dir;
-use std::fs::{create_dir_all, read_dir, File};
-use std::path::PathBuf;
+use std::fs::{copy, create_dir_all, read, read_dir, read_to_string, remove_file, write, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 #[rstest]
 fn convert_file_to_non_existing_file() {
@@ -177,10 +188,595 @@ fn convert_dir_with_deep_hierarchy() {
     assert!(output.join("a/d/8.to").is_file());
 }
 
-fn create_empty_dir() -> PathBuf {
-    let path = temp_dir().join(random::<u64>().to_string());
-    create_dir_all(&path).unwrap();
-    path
+#[rstest]
+fn convert_dir_with_some_failures() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("1.from"));
+    write(create_empty_file(input.join("2.from")), "fail").unwrap();
+
+    let err = convert(&input, Some(&output), "from", "to", |i, _| {
+        let mut content = String::new();
+        i.read_to_string(&mut content)?;
+        if content.is_empty() { Ok(()) } else { Err(io::Error::other("Conversion failed").into()) }
+    }).unwrap_err();
+
+    assert!(err.to_string().contains("Failed to convert 1 of 2 files"));
+    assert!(output.join("1.to").is_file());
+}
+
+#[rstest]
+fn convert_auto_file_to_the_same_dir() {
+    let dir = create_empty_dir();
+    copy("tests/data/red.data", dir.join("red.data")).unwrap();
+    copy("tests/png/blue.png", dir.join("blue.png")).unwrap();
+
+    convert_auto(dir.join("red.data"), None, &DataToPngOptions::default(), &PngToDataOptions::default(), &FileOptions::default()).unwrap();
+    convert_auto(dir.join("blue.png"), None, &DataToPngOptions::default(), &PngToDataOptions::default(), &FileOptions::default()).unwrap();
+
+    assert!(dir.join("red.png").is_file());
+    assert!(dir.join("blue.data").is_file());
+}
+
+#[rstest]
+fn convert_auto_dir_with_mixed_files() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_dir_all(input.join("a")).unwrap();
+    copy("tests/data/red.data", input.join("red.data")).unwrap();
+    copy("tests/png/blue.png", input.join("a/blue.png")).unwrap();
+    // Extension doesn't matter, as long as the content is recognized
+    copy("tests/png/green.png", input.join("a/green.data")).unwrap();
+
+    convert_auto(input, Some(output.clone()), &DataToPngOptions::default(), &PngToDataOptions::default(), &FileOptions::default()).unwrap();
+
+    assert!(output.join("red.png").is_file());
+    assert!(output.join("a/blue.data").is_file());
+    assert!(output.join("a/green.data").is_file());
+}
+
+#[rstest]
+fn convert_auto_dir_into_itself() {
+    let dir = create_empty_dir();
+    copy("tests/data/red.data", dir.join("a.data")).unwrap();
+    copy("tests/png/blue.png", dir.join("a.png")).unwrap();
+
+    let err = convert_auto(dir.clone(), Some(dir.clone()), &DataToPngOptions::default(), &PngToDataOptions::default(), &FileOptions::default()).unwrap_err();
+
+    assert!(matches!(err, Error::OutputInsideInput));
+    assert_eq!(read(dir.join("a.data")).unwrap(), read("tests/data/red.data").unwrap());
+    assert_eq!(read(dir.join("a.png")).unwrap(), read("tests/png/blue.png").unwrap());
+}
+
+#[rstest]
+fn convert_auto_dir_into_dir_inside_of_it() {
+    let input = create_empty_dir();
+    copy("tests/data/red.data", input.join("red.data")).unwrap();
+
+    let err = convert_auto(input.clone(), Some(input.join("out")), &DataToPngOptions::default(), &PngToDataOptions::default(), &FileOptions::default()).unwrap_err();
+
+    assert!(matches!(err, Error::OutputInsideInput));
+    assert!(!input.join("out").exists());
+}
+
+#[rstest]
+fn convert_auto_unrecognized_file() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("empty.data"));
+
+    let err = convert_auto(input, None, &DataToPngOptions::default(), &PngToDataOptions::default(), &FileOptions::default()).unwrap_err();
+
+    assert!(err.to_string().contains("can't be recognized as either DATA or PNG"));
+}
+
+#[rstest]
+fn convert_dir_again_skips_unchanged_files() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "one").unwrap();
+    write(create_empty_file(input.join("a/2.from")), "two").unwrap();
+    convert_counting(&input, &output, false);
+
+    let converted = convert_counting(&input, &output, false);
+
+    assert_eq!(converted, 0);
+    assert!(output.join(MANIFEST_FILE_NAME).is_file());
+}
+
+#[rstest]
+fn convert_dir_again_converts_changed_files() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "one").unwrap();
+    write(create_empty_file(input.join("2.from")), "two").unwrap();
+    convert_counting(&input, &output, false);
+    write(input.join("2.from"), "changed").unwrap();
+
+    let converted = convert_counting(&input, &output, false);
+
+    assert_eq!(converted, 1);
+}
+
+#[rstest]
+fn convert_dir_again_skips_touched_files_with_same_content() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "one").unwrap();
+    convert_counting(&input, &output, false);
+    let file = File::options().write(true).open(input.join("1.from")).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+
+    let converted = convert_counting(&input, &output, false);
+
+    assert_eq!(converted, 0);
+}
+
+#[rstest]
+fn convert_dir_again_converts_files_with_missing_output() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "one").unwrap();
+    write(create_empty_file(input.join("2.from")), "two").unwrap();
+    convert_counting(&input, &output, false);
+    remove_file(output.join("1.to")).unwrap();
+
+    let converted = convert_counting(&input, &output, false);
+
+    assert_eq!(converted, 1);
+    assert!(output.join("1.to").is_file());
+}
+
+#[rstest]
+fn convert_dir_again_converts_files_when_options_change() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    copy("tests/png/red.png", input.join("red.png")).unwrap();
+    let data_options = DataToPngOptions::default();
+    convert_auto(input.clone(), Some(output.clone()), &data_options, &PngToDataOptions::default(), &FileOptions::default()).unwrap();
+    write(output.join("red.data"), "old").unwrap();
+    let png_options = PngToDataOptions { palette_fallback: Some([0, 0, 0, 0]), ..Default::default() };
+
+    convert_auto(input.clone(), Some(output.clone()), &data_options, &png_options, &FileOptions::default()).unwrap();
+
+    assert_ne!(read(output.join("red.data")).unwrap(), b"old");
+}
+
+#[rstest]
+fn convert_dir_again_converts_files_when_fingerprint_changes() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "one").unwrap();
+    convert_counting_with_fingerprint(&input, &output, false, 1);
+
+    let converted = convert_counting_with_fingerprint(&input, &output, false, 2);
+
+    assert_eq!(converted, 1);
+}
+
+#[rstest]
+fn convert_dir_again_without_fingerprint_converts_all_files() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "one").unwrap();
+    write(create_empty_file(input.join("2.from")), "two").unwrap();
+    let converted = AtomicUsize::new(0);
+    let convert_fn = |_: &mut _, _: &mut _| {
+        converted.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    };
+    convert_with_options(&input, Some(&output), "from", "to", &FileOptions::default(), convert_fn).unwrap();
+
+    convert_with_options(&input, Some(&output), "from", "to", &FileOptions::default(), convert_fn).unwrap();
+
+    assert_eq!(converted.into_inner(), 4);
+    assert!(!output.join(MANIFEST_FILE_NAME).exists());
+}
+
+#[rstest]
+fn convert_dir_again_with_force_converts_all_files() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "one").unwrap();
+    write(create_empty_file(input.join("2.from")), "two").unwrap();
+    convert_counting(&input, &output, false);
+
+    let converted = convert_counting(&input, &output, true);
+
+    assert_eq!(converted, 2);
+}
+
+#[rstest]
+fn convert_dir_again_retries_failed_files() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "fail").unwrap();
+    let options = FileOptions::default();
+    convert_with_fingerprint(&input, Some(&output), "from", "to", &options, 0, |_, _| Err(io::Error::other("Conversion failed").into())).unwrap_err();
+
+    let converted = convert_counting(&input, &output, false);
+
+    assert_eq!(converted, 1);
+}
+
+#[rstest]
+fn convert_file_failure_keeps_existing_output() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("input.from"));
+    let output = dir.join("output.to");
+    write(&output, "old").unwrap();
+
+    convert(&input, Some(&output), "from", "to", |_, o| {
+        o.write_all(b"partial")?;
+        Err(io::Error::other("Conversion failed").into())
+    }).unwrap_err();
+
+    assert_eq!(read_to_string(&output).unwrap(), "old");
+    assert_eq!(read_dir(&dir).unwrap().count(), 2);
+}
+
+#[rstest]
+fn convert_file_with_overwrite_policy() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("input.from"));
+    let output = dir.join("output.to");
+    write(&output, "old").unwrap();
+
+    convert_with_policy(&input, &output, OverwritePolicy::Overwrite).unwrap();
+
+    assert_eq!(read_to_string(&output).unwrap(), "new");
+    assert_eq!(read_dir(&dir).unwrap().count(), 2);
+}
+
+#[rstest]
+fn convert_file_with_skip_existing_policy() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("input.from"));
+    let output = dir.join("output.to");
+    write(&output, "old").unwrap();
+
+    convert_with_policy(&input, &output, OverwritePolicy::SkipExisting).unwrap();
+
+    assert_eq!(read_to_string(&output).unwrap(), "old");
+}
+
+#[rstest]
+fn convert_file_with_backup_policy() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("input.from"));
+    let output = dir.join("output.to");
+    write(&output, "old").unwrap();
+
+    convert_with_policy(&input, &output, OverwritePolicy::Backup).unwrap();
+
+    assert_eq!(read_to_string(&output).unwrap(), "new");
+    assert_eq!(read_to_string(dir.join("output.to.bak")).unwrap(), "old");
+}
+
+#[rstest]
+fn convert_file_with_fail_if_exists_policy() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("input.from"));
+    let output = dir.join("output.to");
+    write(&output, "old").unwrap();
+
+    let err = convert_with_policy(&input, &output, OverwritePolicy::FailIfExists).unwrap_err();
+
+    assert!(err.to_string().contains("Output file already exists"));
+    assert_eq!(read_to_string(&output).unwrap(), "old");
+}
+
+#[rstest]
+fn convert_file_with_fail_if_exists_policy_to_non_existing_file() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("input.from"));
+    let output = dir.join("output.to");
+
+    convert_with_policy(&input, &output, OverwritePolicy::FailIfExists).unwrap();
+
+    assert_eq!(read_to_string(&output).unwrap(), "new");
+}
+
+#[rstest]
+fn convert_dir_with_skip_existing_policy() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("1.from"));
+    create_empty_file(input.join("2.from"));
+    write(output.join("1.to"), "old").unwrap();
+
+    let observer = Arc::new(CountingObserver::default());
+    let options = FileOptions { overwrite: OverwritePolicy::SkipExisting, observer: Some(observer.clone()), ..Default::default() };
+
+    convert_with_fingerprint(&input, Some(&output), "from", "to", &options, 0, |_, o| Ok(o.write_all(b"new")?)).unwrap();
+
+    assert_eq!(read_to_string(output.join("1.to")).unwrap(), "old");
+    assert_eq!(read_to_string(output.join("2.to")).unwrap(), "new");
+    assert_eq!(observer.converted.load(Ordering::Relaxed), 1);
+    assert_eq!(observer.skipped.load(Ordering::Relaxed), 1);
+    let manifest = Manifest::load(&output);
+    assert!(manifest.get(Path::new("1.from")).is_none());
+    assert!(manifest.get(Path::new("2.from")).is_some());
+}
+
+#[rstest]
+fn convert_dir_again_converts_files_skipped_as_existing() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("1.from"));
+    create_empty_file(input.join("2.from"));
+    write(output.join("1.to"), "old").unwrap();
+    convert_with_policy(&input, &output, OverwritePolicy::SkipExisting).unwrap();
+
+    let converted = convert_counting(&input, &output, false);
+
+    assert_eq!(converted, 1);
+    assert_eq!(read_to_string(output.join("1.to")).unwrap(), "");
+}
+
+#[rstest]
+fn convert_file_to_existing_dir() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("input.from"));
+    let output = create_empty_dir();
+
+    convert(&input, Some(&output), "from", "to", |_, _| Ok(())).unwrap();
+
+    assert!(output.join("input.to").is_file());
+}
+
+#[rstest]
+fn convert_file_with_multiple_dots_to_the_same_dir() {
+    let dir = create_empty_dir();
+    let input = create_empty_file(dir.join("tiles.v2.from"));
+
+    convert(&input, None, "from", "to", |_, _| Ok(())).unwrap();
+
+    assert!(dir.join("tiles.v2.to").is_file());
+}
+
+#[rstest]
+fn convert_dir_with_multiple_dots_in_file_names() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("tiles.from"));
+    create_empty_file(input.join("tiles.v2.from"));
+    create_empty_file(input.join("a.b/tiles.v3.from"));
+
+    convert(&input, Some(&output), "from", "to", |_, _| Ok(())).unwrap();
+
+    assert!(output.join("tiles.to").is_file());
+    assert!(output.join("tiles.v2.to").is_file());
+    assert!(output.join("a.b/tiles.v3.to").is_file());
+}
+
+#[cfg(unix)]
+#[rstest]
+fn convert_dir_with_non_utf8_file_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join(OsStr::from_bytes(b"\xff\xfe.from")));
+
+    convert(&input, Some(&output), "from", "to", |_, _| Ok(())).unwrap();
+
+    assert!(output.join(OsStr::from_bytes(b"\xff\xfe.to")).is_file());
+}
+
+#[rstest]
+fn convert_dir_with_colliding_outputs() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("1.from"));
+    create_empty_file(input.join("2.from"));
+    create_empty_file(input.join("2.FROM"));
+
+    let err = convert(&input, Some(&output), "from", "to", |_, _| Ok(())).unwrap_err();
+
+    assert!(err.to_string().contains("Failed to convert 2 of 3 files"));
+    assert!(output.join("1.to").is_file());
+    assert!(!output.join("2.to").exists());
+}
+
+#[rstest]
+fn convert_dir_with_output_overwriting_another_input() {
+    let input = create_empty_dir();
+    write(create_empty_file(input.join("1.from")), "one").unwrap();
+    write(create_empty_file(input.join("a/1.from")), "two").unwrap();
+
+    let err = convert(&input, Some(&input.join("a")), "from", "from", |_, o| Ok(o.write_all(b"new")?)).unwrap_err();
+
+    assert!(err.to_string().contains("Failed to convert 1 of 2 files"));
+    assert_eq!(read_to_string(input.join("a/1.from")).unwrap(), "two");
+    assert_eq!(read_to_string(input.join("a/a/1.from")).unwrap(), "new");
+}
+
+#[rstest]
+fn convert_auto_dir_with_colliding_outputs() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    copy("tests/png/blue.png", input.join("blue.png")).unwrap();
+    // Recognized as PNG, so it would be converted into the same blue.data
+    copy("tests/png/green.png", input.join("blue.data")).unwrap();
+
+    let err = convert_auto(input, Some(output.clone()), &DataToPngOptions::default(), &PngToDataOptions::default(), &FileOptions::default()).unwrap_err();
+
+    assert!(err.to_string().contains("Failed to convert 2 of 2 files"));
+    assert!(!output.join("blue.data").exists());
+}
+
+#[rstest]
+fn convert_dir_with_files_without_extension() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("LICENSE"));
+    create_empty_file(input.join("a/README"));
+    create_empty_file(input.join("a/1.from"));
+
+    convert(&input, Some(&output), "from", "to", |_, _| Ok(())).unwrap();
+
+    assert!(output.join("a/1.to").is_file());
+}
+
+#[rstest]
+fn convert_dir_with_include_and_exclude_patterns() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("1.from"));
+    create_empty_file(input.join("Graphics/Atlases/Gameplay/2.from"));
+    create_empty_file(input.join("Graphics/Atlases/Gameplay/a/3.from"));
+    create_empty_file(input.join("Graphics/Atlases/Gameplay/Portraits/4.from"));
+    create_empty_file(input.join("Graphics/Atlases/Gui/5.from"));
+    let scan = ScanOptions {
+        include: vec!["Graphics/Atlases/Gameplay/**".into()],
+        exclude: vec!["**/Portraits/**".into()],
+        ..Default::default()
+    };
+
+    convert_with_scan(&input, &output, scan).unwrap();
+
+    assert!(!output.join("1.to").exists());
+    assert!(output.join("Graphics/Atlases/Gameplay/2.to").is_file());
+    assert!(output.join("Graphics/Atlases/Gameplay/a/3.to").is_file());
+    assert!(!output.join("Graphics/Atlases/Gameplay/Portraits/4.to").exists());
+    assert!(!output.join("Graphics/Atlases/Gui/5.to").exists());
+}
+
+#[rstest]
+fn convert_dir_with_excluded_directory() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("a/1.from"));
+    create_empty_file(input.join("b/2.from"));
+    let scan = ScanOptions { exclude: vec!["b".into()], ..Default::default() };
+
+    convert_with_scan(&input, &output, scan).unwrap();
+
+    assert!(output.join("a/1.to").is_file());
+    assert!(!output.join("b/2.to").exists());
+}
+
+#[rstest]
+fn convert_dir_with_invalid_pattern() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    let scan = ScanOptions { include: vec!["[".into()], ..Default::default() };
+
+    let err = convert_with_scan(&input, &output, scan).unwrap_err();
+
+    assert!(err.to_string().contains("Invalid glob pattern '['"));
+}
+
+#[rstest]
+#[case(Some(0), 1)]
+#[case(Some(1), 2)]
+#[case(None, 20)]
+fn convert_dir_with_max_depth(#[case] max_depth: Option<usize>, #[case] expected: usize) {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    let mut dir = input.clone();
+    for _ in 0..20 {
+        create_empty_file(dir.join("1.from"));
+        dir = dir.join("a");
+    }
+
+    let scan = ScanOptions { max_depth, ..Default::default() };
+    convert_with_scan(&input, &output, scan).unwrap();
+
+    assert_eq!(count_files(&output, "to"), expected);
+}
+
+#[rstest]
+#[case(false, 1)]
+#[case(true, 3)]
+fn convert_dir_with_hidden_files(#[case] hidden: bool, #[case] expected: usize) {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    create_empty_file(input.join("1.from"));
+    create_empty_file(input.join(".2.from"));
+    create_empty_file(input.join(".a/3.from"));
+
+    convert_with_scan(&input, &output, ScanOptions { hidden, ..Default::default() }).unwrap();
+
+    assert_eq!(count_files(&output, "to"), expected);
+}
+
+#[cfg(unix)]
+#[rstest]
+#[case(false, 1)]
+#[case(true, 3)]
+fn convert_dir_with_symlinks(#[case] follow_symlinks: bool, #[case] expected: usize) {
+    use std::os::unix::fs::symlink;
+
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    let outside = create_empty_dir();
+    create_empty_file(input.join("a/1.from"));
+    create_empty_file(outside.join("2.from"));
+    symlink(&outside, input.join("outside")).unwrap();
+    symlink(outside.join("2.from"), input.join("a/linked.from")).unwrap();
+    symlink(input.join("missing.from"), input.join("broken.from")).unwrap();
+    // Would be scanned forever without detecting the cycle
+    symlink(&input, input.join("a/cycle")).unwrap();
+
+    convert_with_scan(&input, &output, ScanOptions { follow_symlinks, ..Default::default() }).unwrap();
+
+    assert_eq!(count_files(&output, "to"), expected);
+}
+
+/// Converts the directory with the given scan options.
+fn convert_with_scan(input: &PathBuf, output: &PathBuf, scan: ScanOptions) -> Result<()> {
+    let options = FileOptions { scan, ..Default::default() };
+    convert_with_options(input, Some(output), "from", "to", &options, |_, _| Ok(()))
+}
+
+/// Counts files with the given extension in the whole directory tree.
+fn count_files(dir: &PathBuf, ext: &str) -> usize {
+    read_dir(dir).unwrap().map(|entry| {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            count_files(&path, ext)
+        } else {
+            path.extension().is_some_and(|e| e == ext) as usize
+        }
+    }).sum()
+}
+
+/// Converts with the given overwrite policy, writing "new" into every output.
+fn convert_with_policy(input: &PathBuf, output: &PathBuf, overwrite: OverwritePolicy) -> Result<()> {
+    let options = FileOptions { overwrite, ..Default::default() };
+    convert_with_fingerprint(input, Some(output), "from", "to", &options, 0, |_, o| Ok(o.write_all(b"new")?))
+}
+
+/// Converts the directory, returning how many files were actually converted.
+fn convert_counting(input: &PathBuf, output: &PathBuf, force: bool) -> usize {
+    convert_counting_with_fingerprint(input, output, force, 0)
+}
+
+/// Converts the directory with the given fingerprint, returning how many files were actually converted.
+fn convert_counting_with_fingerprint(input: &PathBuf, output: &PathBuf, force: bool, fingerprint: u64) -> usize {
+    let converted = AtomicUsize::new(0);
+    let options = FileOptions { force, ..Default::default() };
+    convert_with_fingerprint(input, Some(output), "from", "to", &options, fingerprint, |_, _| {
+        converted.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }).unwrap();
+    converted.into_inner()
+}
+
+/// Counts files converted and skipped, as reported to the observer.
+#[derive(Default)]
+struct CountingObserver {
+    converted: AtomicUsize,
+    skipped: AtomicUsize,
+}
+
+impl Observer for CountingObserver {
+    fn file_finished(&self, _input: &Path, _output: &Path, _bytes: u64, skipped: bool) {
+        let count = if skipped { &self.skipped } else { &self.converted };
+        count.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 fn create_empty_file(path: PathBuf) -> PathBuf {
@@ -0,0 +1,53 @@
+use celeste_converter::log;
+use celeste_converter::log::{set_logger, set_max_level, Level};
+use rstest::rstest;
+use std::sync::{Arc, Mutex};
+use std::thread::current;
+
+#[rstest]
+#[case(Some(Level::Trace), &[Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace])]
+#[case(Some(Level::Info), &[Level::Error, Level::Warn, Level::Info])]
+#[case(Some(Level::Error), &[Level::Error])]
+#[case(None, &[])]
+fn logger_receives_messages_up_to_max_level(#[case] max_level: Option<Level>, #[case] expected: &[Level]) {
+    let records = capture(max_level, || {
+        log!(error: "error {}", 1);
+        log!(warn: "warn {}", 2);
+        log!("info {}", 3);
+        log!(debug: "debug {}", 4);
+        log!(trace: "trace {}", 5);
+    });
+
+    let levels: Vec<Level> = records.iter().map(|(level, _)| *level).collect();
+    assert_eq!(levels, expected);
+}
+
+#[rstest]
+fn logger_receives_formatted_message() {
+    let records = capture(Some(Level::Info), || {
+        log!(info: "{} of {}", 1, 2);
+    });
+
+    assert_eq!(records, vec![(Level::Info, "1 of 2".to_string())]);
+}
+
+/// Runs the function with logger capturing messages of the current thread only,
+/// as tests running in parallel share the same logger.
+fn capture<F: FnOnce()>(max_level: Option<Level>, f: F) -> Vec<(Level, String)> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let thread_name = current().name().unwrap_or_default().to_string();
+    let logger_records = records.clone();
+    set_logger(move |record| {
+        if record.thread == thread_name {
+            logger_records.lock().unwrap().push((record.level, record.message.to_string()));
+        }
+    });
+    set_max_level(max_level);
+
+    f();
+
+    records.lock().unwrap().clone()
+}
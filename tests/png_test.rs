@@ -1,6 +1,6 @@
 use ColorType::*;
 use png::BitDepth::*;
-use png::ColorType;
+use png::{BitDepth, ColorType};
 use rstest::rstest;
 use celeste_converter::png::Png;
 
@@ -359,5 +359,132 @@ fn eight_bit_quadruple_channel_into_multiple_chunks_with_remainder() {
     assert_eq!(png_chunks[2].len, 1);
 }
 
+#[rstest]
+fn indexed_with_trns_into_rgba() {
+    let palette = vec![0x10, 0x11, 0x12, 0x20, 0x21, 0x22, 0x30, 0x31, 0x32];
+    let png = Png::with_trns(3, 1, Indexed, Eight, vec![0, 1, 2], Some(palette), Some(vec![0x00, 0x80])).unwrap();
+
+    assert!(png.has_alpha());
+    assert_eq!(png.as_chunk().rgba(), [
+        0x10, 0x11, 0x12, 0x00,
+        0x20, 0x21, 0x22, 0x80,
+        0x30, 0x31, 0x32, 0xFF,
+    ]);
+}
+
+#[rstest]
+fn indexed_with_opaque_trns_has_no_alpha() {
+    let palette = vec![0x10, 0x11, 0x12];
+    let png = Png::with_trns(1, 1, Indexed, Eight, vec![0], Some(palette), Some(vec![0xFF])).unwrap();
+
+    assert!(!png.has_alpha());
+}
+
+#[rstest]
+fn grayscale_with_trns_into_rgba() {
+    let png = Png::with_trns(4, 1, Grayscale, Two, vec![0b00011011], None, Some(vec![2])).unwrap();
+
+    assert!(png.has_alpha());
+    assert_eq!(png.as_chunk().rgba(), [
+        0x00, 0x00, 0x00, 0xFF,
+        0x55, 0x55, 0x55, 0xFF,
+        0xAA, 0xAA, 0xAA, 0x00,
+        0xFF, 0xFF, 0xFF, 0xFF,
+    ]);
+}
+
+#[rstest]
+fn rgb_with_trns_into_rgba() {
+    let data = vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+    let png = Png::with_trns(2, 1, Rgb, Eight, data, None, Some(vec![0x04, 0x05, 0x06])).unwrap();
+
+    assert!(png.has_alpha());
+    assert_eq!(png.as_chunk().rgba(), [0x01, 0x02, 0x03, 0xFF, 0x04, 0x05, 0x06, 0x00]);
+}
+
+#[rstest]
+fn sixteen_bit_rgb_with_trns_matches_full_samples() {
+    let data = vec![
+        0x01, 0x00, 0x02, 0x00, 0x03, 0x00,
+        0x01, 0x01, 0x02, 0x00, 0x03, 0x00,
+    ];
+    let trns = vec![0x01, 0x00, 0x02, 0x00, 0x03, 0x00];
+    let png = Png::with_trns(2, 1, Rgb, Sixteen, data, None, Some(trns)).unwrap();
+
+    assert_eq!(png.as_chunk().rgba(), [0x01, 0x02, 0x03, 0x00, 0x01, 0x02, 0x03, 0xFF]);
+}
+
+#[rstest]
+fn rgb_with_malformed_trns() {
+    let result = Png::with_trns(1, 1, Rgb, Eight, vec![0, 0, 0], None, Some(vec![0]));
+
+    assert!(result.is_err());
+}
+
+#[rstest]
+#[case::empty(Eight, vec![])]
+#[case::truncated_entry(Eight, vec![0x10, 0x11, 0x12, 0x20])]
+#[case::too_many_entries(One, vec![0x10, 0x11, 0x12, 0x20, 0x21, 0x22, 0x30, 0x31, 0x32])]
+fn indexed_with_malformed_palette(#[case] bit_depth: BitDepth, #[case] palette: Vec<u8>) {
+    let result = Png::new(1, 1, Indexed, bit_depth, vec![0], Some(palette));
+
+    assert!(result.is_err());
+}
+
+#[rstest]
+fn indexed_with_odd_palette_length() {
+    // Palette may have fewer entries than indices allow, as long as the image only uses existing ones
+    let palette = vec![0x10, 0x11, 0x12, 0x20, 0x21, 0x22, 0x30, 0x31, 0x32];
+    let png = Png::new(4, 1, Indexed, Two, vec![0b00011000], Some(palette)).unwrap();
+
+    png.check_palette().unwrap();
+    assert_eq!(png.as_chunk().rgb(), [0x10, 0x11, 0x12, 0x20, 0x21, 0x22, 0x30, 0x31, 0x32, 0x10, 0x11, 0x12]);
+}
+
+#[rstest]
+fn indexed_with_index_outside_of_palette() {
+    let palette = vec![0x10, 0x11, 0x12, 0x20, 0x21, 0x22];
+    let png = Png::new(3, 1, Indexed, Eight, vec![0, 1, 7], Some(palette)).unwrap();
+
+    let err = png.check_palette().unwrap_err();
+
+    assert!(err.to_string().contains("palette index 7"));
+}
+
+#[rstest]
+fn indexed_with_index_outside_of_palette_uses_fallback() {
+    let palette = vec![0x10, 0x11, 0x12];
+    let mut png = Png::new(2, 1, Indexed, Eight, vec![0, 200], Some(palette)).unwrap();
+    png.palette_fallback = Some([0xAA, 0xBB, 0xCC, 0x00]);
+
+    png.check_palette().unwrap();
+    assert!(png.has_alpha());
+    assert_eq!(png.as_chunk().rgba(), [0x10, 0x11, 0x12, 0xFF, 0xAA, 0xBB, 0xCC, 0x00]);
+}
+
+#[rstest]
+fn indexed_with_index_outside_of_palette_in_later_chunk() {
+    let palette = vec![0x10, 0x11, 0x12];
+    let mut data = vec![0; 0x10000];
+    data.push(1);
+    let png = Png::new(data.len(), 1, Indexed, Eight, data, Some(palette)).unwrap();
+
+    assert!(png.check_palette().is_err());
+}
+
+#[rstest]
+fn indexed_with_sixteen_bit_depth() {
+    let result = Png::new(1, 1, Indexed, Sixteen, vec![0, 0], Some(vec![0x10, 0x11, 0x12]));
+
+    assert!(result.is_err());
+}
+
+#[rstest]
+fn truncated_pixel_data() {
+    let result = Png::new(2, 2, Rgb, Eight, vec![0; 11], None);
+
+    assert!(result.is_err());
+}
+
 // TODO: add tests for 16-bit chunks
 // TODO: add tests for RGB and RGBA data conversion of all 15 PNG formats
@@ -0,0 +1,141 @@
+mod common;
+
+use celeste_converter::error::{Error, Result};
+use celeste_converter::file::{convert_with_fingerprint, FileOptions};
+use celeste_converter::progress::{format_bytes, format_duration, Observer};
+use common::create_empty_dir;
+use rstest::rstest;
+use std::fs::write;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[rstest]
+fn observer_receives_events_of_dir_conversion() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(input.join("1.from"), "one").unwrap();
+    write(input.join("2.from"), "fail").unwrap();
+    let observer = Arc::new(RecordingObserver::default());
+
+    convert_observed(&input, &output, observer.clone()).unwrap_err();
+
+    let mut events = observer.events();
+    events[1..5].sort();
+    assert_eq!(events, vec![
+        "started 2 files, 7 bytes".to_string(),
+        "failed 2.from, 4 bytes".to_string(),
+        "file started 1.from".to_string(),
+        "file started 2.from".to_string(),
+        "finished 1.from into 1.to, 3 bytes".to_string(),
+        "finished".to_string(),
+    ]);
+}
+
+#[rstest]
+fn observer_receives_skipped_files() {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    write(input.join("1.from"), "one").unwrap();
+    convert_observed(&input, &output, Arc::new(RecordingObserver::default())).unwrap();
+    let observer = Arc::new(RecordingObserver::default());
+
+    convert_observed(&input, &output, observer.clone()).unwrap();
+
+    assert!(observer.events().contains(&"skipped 1.from, 3 bytes".to_string()));
+}
+
+#[rstest]
+fn observer_receives_events_of_file_conversion() {
+    let dir = create_empty_dir();
+    let input = dir.join("1.from");
+    write(&input, "one").unwrap();
+    let observer = Arc::new(RecordingObserver::default());
+
+    convert_observed(&input, &dir.join("1.to"), observer.clone()).unwrap();
+
+    assert_eq!(observer.events(), vec![
+        "started 1 files, 3 bytes".to_string(),
+        "file started 1.from".to_string(),
+        "finished 1.from into 1.to, 3 bytes".to_string(),
+        "finished".to_string(),
+    ]);
+}
+
+#[rstest]
+#[case(0, "0 B")]
+#[case(1023, "1023 B")]
+#[case(1024, "1.0 KiB")]
+#[case(1536, "1.5 KiB")]
+#[case(5 * 1024 * 1024, "5.0 MiB")]
+#[case(3 * 1024 * 1024 * 1024, "3.0 GiB")]
+fn bytes_are_formatted(#[case] bytes: u64, #[case] expected: &str) {
+    assert_eq!(format_bytes(bytes), expected);
+}
+
+#[rstest]
+#[case(0, "0s")]
+#[case(59, "59s")]
+#[case(60, "1m 0s")]
+#[case(3599, "59m 59s")]
+#[case(7260, "2h 1m")]
+fn durations_are_formatted(#[case] seconds: u64, #[case] expected: &str) {
+    assert_eq!(format_duration(Duration::from_secs(seconds)), expected);
+}
+
+/// Observer keeping every event as text.
+#[derive(Default)]
+struct RecordingObserver {
+    events: Mutex<Vec<String>>,
+}
+
+impl RecordingObserver {
+    fn events(&self) -> Vec<String> {
+        self.events.lock().unwrap().clone()
+    }
+
+    fn push(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl Observer for RecordingObserver {
+    fn started(&self, files: usize, bytes: u64) {
+        self.push(format!("started {files} files, {bytes} bytes"));
+    }
+
+    fn file_started(&self, input: &Path) {
+        self.push(format!("file started {}", name(input)));
+    }
+
+    fn file_finished(&self, input: &Path, output: &Path, bytes: u64, skipped: bool) {
+        if skipped {
+            self.push(format!("skipped {}, {bytes} bytes", name(input)));
+        } else {
+            self.push(format!("finished {} into {}, {bytes} bytes", name(input), name(output)));
+        }
+    }
+
+    fn file_failed(&self, input: &Path, bytes: u64, _error: &Error) {
+        self.push(format!("failed {}, {bytes} bytes", name(input)));
+    }
+
+    fn finished(&self) {
+        self.push("finished".to_string());
+    }
+}
+
+/// Converts with the observer, where inputs containing "fail" fail to convert.
+fn convert_observed(input: &PathBuf, output: &PathBuf, observer: Arc<RecordingObserver>) -> Result<()> {
+    let options = FileOptions { observer: Some(observer), ..Default::default() };
+    convert_with_fingerprint(input, Some(output), "from", "to", &options, 0, |i, _| {
+        let mut content = String::new();
+        i.read_to_string(&mut content)?;
+        if content == "fail" { Err(io::Error::other("Conversion failed").into()) } else { Ok(()) }
+    })
+}
+
+fn name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().to_string()
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use celeste_converter::file::{convert_with_options, FileOptions};
+use celeste_converter::rayon::{build_pool, init_rayon};
+use common::create_empty_dir;
+use rstest::rstest;
+use std::collections::HashSet;
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use std::thread::current;
+
+#[rstest]
+fn init_rayon_again_fails_without_panic() {
+    let _ = init_rayon(2);
+
+    assert!(init_rayon(2).is_err());
+}
+
+#[rstest]
+#[case(1)]
+#[case(3)]
+fn convert_dir_runs_in_thread_pool(#[case] threads: usize) {
+    let input = create_empty_dir();
+    let output = create_empty_dir();
+    for i in 0..20 {
+        File::create(input.join(format!("{i}.from"))).unwrap();
+    }
+    let pool = Arc::new(build_pool(threads).unwrap());
+    let options = FileOptions { thread_pool: Some(pool), ..Default::default() };
+
+    let thread_names = Mutex::new(HashSet::new());
+    convert_with_options(&input, Some(&output), "from", "to", &options, |_, _| {
+        thread_names.lock().unwrap().insert(current().name().unwrap_or_default().to_string());
+        Ok(())
+    }).unwrap();
+
+    let thread_names = thread_names.into_inner().unwrap();
+    assert!(thread_names.len() <= threads);
+    assert!(thread_names.iter().all(|name| name.starts_with("thread-")), "Unexpected threads {thread_names:?}");
+}
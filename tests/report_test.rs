@@ -0,0 +1,125 @@
+mod common;
+
+use celeste_converter::convert::{DataToPngOptions, Format, PngToDataOptions};
+use celeste_converter::file::{convert_auto, FileOptions, OverwritePolicy};
+use celeste_converter::report::{Report, ReportFormat, ReportStatus};
+use common::create_empty_dir;
+use rstest::rstest;
+use std::fs::{copy, write};
+use std::path::Path;
+use std::sync::Arc;
+
+#[rstest]
+fn report_has_entry_for_every_file() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/data/red.data", input.join("red.data")).unwrap();
+    copy("tests/png/transparent.png", input.join("alpha.png")).unwrap();
+    write(input.join("broken.data"), [1, 2, 3]).unwrap();
+
+    let report = convert_reported(&input, &output);
+
+    let entries = report.entries();
+    assert_eq!(entries.len(), 3);
+
+    let alpha = &entries[0];
+    assert_eq!(alpha.input, input.join("alpha.png"));
+    assert_eq!(alpha.output, Some(output.join("alpha.data")));
+    assert_eq!(alpha.status, ReportStatus::Converted);
+    assert_eq!(alpha.input_format, Some(Format::Png));
+    assert_eq!(alpha.direction(), Some("png2data"));
+    assert_eq!(alpha.has_alpha, Some(true));
+    assert!(alpha.output_bytes.is_some_and(|b| b > 0));
+    assert!(alpha.compression_ratio().is_some());
+
+    let broken = &entries[1];
+    assert_eq!(broken.status, ReportStatus::Failed);
+    assert_eq!(broken.input_bytes, 3);
+    assert_eq!(broken.output, None);
+    assert!(broken.error.as_ref().is_some_and(|e| e.contains("can't be recognized")));
+
+    let red = &entries[2];
+    assert_eq!(red.direction(), Some("data2png"));
+    assert_eq!((red.width, red.height, red.has_alpha), (Some(32), Some(32), Some(false)));
+}
+
+#[rstest]
+fn report_marks_skipped_files() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/data/red.data", input.join("red.data")).unwrap();
+    convert_reported(&input, &output);
+
+    let report = convert_reported(&input, &output);
+
+    assert_eq!(report.entries()[0].status, ReportStatus::Skipped);
+}
+
+#[rstest]
+fn report_marks_files_skipped_as_existing() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/data/red.data", input.join("red.data")).unwrap();
+    write(output.join("red.png"), "old").unwrap();
+    let report = Arc::new(Report::new());
+    let options = FileOptions { overwrite: OverwritePolicy::SkipExisting, observer: Some(report.clone()), ..Default::default() };
+
+    convert_auto(input, Some(output), &DataToPngOptions::default(), &PngToDataOptions::default(), &options).unwrap();
+
+    assert_eq!(report.entries()[0].status, ReportStatus::Skipped);
+}
+
+#[rstest]
+fn report_is_written_as_json() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/data/red.data", input.join("red.data")).unwrap();
+    write(input.join("broken.data"), [1, 2, 3]).unwrap();
+    let report = convert_reported(&input, &output);
+
+    let json = write_report(&report, ReportFormat::Json);
+
+    let lines: Vec<&str> = json.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "[");
+    assert!(lines[1].contains(r#""status": "failed""#));
+    assert!(lines[1].contains(r#""width": null"#));
+    assert!(lines[1].ends_with("},"));
+    assert!(lines[2].contains(r#""direction": "data2png", "width": 32, "height": 32, "has_alpha": false"#));
+    assert!(lines[2].contains(r#""error": null}"#));
+    assert_eq!(lines[3], "]");
+}
+
+#[rstest]
+fn report_is_written_as_csv() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/data/red.data", input.join("red, \"quoted\".data")).unwrap();
+    let report = convert_reported(&input, &output);
+
+    let csv = write_report(&report, ReportFormat::Csv);
+
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "input,output,status,direction,width,height,has_alpha,input_bytes,output_bytes,compression_ratio,duration_ms,error");
+    assert!(lines[1].starts_with(&format!("\"{}\",", input.join("red, \"\"quoted\"\".data").display())));
+    assert!(lines[1].contains(",converted,data2png,32,32,false,29,"));
+    assert!(lines[1].ends_with(','));
+}
+
+#[rstest]
+#[case("report.json", ReportFormat::Json)]
+#[case("report.CSV", ReportFormat::Csv)]
+#[case("report", ReportFormat::Json)]
+fn report_format_is_picked_by_extension(#[case] path: &str, #[case] expected: ReportFormat) {
+    assert_eq!(ReportFormat::from_path(Path::new(path)), expected);
+}
+
+fn convert_reported(input: &Path, output: &Path) -> Arc<Report> {
+    let report = Arc::new(Report::new());
+    let options = FileOptions { observer: Some(report.clone()), ..Default::default() };
+    // Some files may fail on purpose
+    let _ = convert_auto(input.to_path_buf(), Some(output.to_path_buf()), &DataToPngOptions::default(), &PngToDataOptions::default(), &options);
+    report
+}
+
+fn write_report(report: &Report, format: ReportFormat) -> String {
+    let mut output = Vec::new();
+    report.write(&mut output, format).unwrap();
+    String::from_utf8(output).unwrap()
+}
@@ -165,7 +165,7 @@ fn sixteen_bit_single_byte() {
 fn sixteen_bit_multiple_bytes() {
     let data = [0x64, 0x00, 0xC8, 0x00];
     let unpacked = unpack(&data, 2, 2, Sixteen);
-    assert_eq!(unpacked, [100, 200]);
+    assert_eq!(unpacked, [100, 199]);
 }
 
 #[rstest]
@@ -186,5 +186,12 @@ fn sixteen_bit_max_values() {
 fn sixteen_bit_precision_loss() {
     let data = [0x00, 0xAA, 0x64, 0xBB, 0xC8, 0xCC, 0xFF, 0xDD];
     let unpacked = unpack(&data, 4, 4, Sixteen);
-    assert_eq!(unpacked, [0, 100, 200, 255]);
+    assert_eq!(unpacked, [1, 100, 200, 255]);
+}
+
+#[rstest]
+fn sixteen_bit_rounding() {
+    let data = [0x00, 0x80, 0x00, 0x81, 0x80, 0x80, 0xFF, 0x7E, 0xFF, 0x7F];
+    let unpacked = unpack(&data, 5, 5, Sixteen);
+    assert_eq!(unpacked, [0, 1, 128, 254, 255]);
 }
@@ -0,0 +1,111 @@
+use celeste_converter::data::DataLimits;
+use celeste_converter::verify::{verify, verify_with_limits, IssueKind};
+use rstest::rstest;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+#[rstest]
+#[case::red("red")]
+#[case::transparent("transparent")]
+#[case::multi_color("multi-color")]
+#[case::big_test_no_background("big-test-no-background")]
+#[case::ffmpeg_rgb24("ffmpeg/rgb24")]
+#[case::ffmpeg_rgba("ffmpeg/rgba")]
+fn verify_original_data_is_valid(#[case] case: &str) {
+    let mut input = BufReader::new(File::open(format!("tests/data/{case}.data")).unwrap());
+
+    let verification = verify(&mut input).unwrap();
+
+    assert!(verification.is_valid(), "Unexpected issue: {}", verification.issues[0]);
+}
+
+#[rstest]
+fn verify_reports_header_parameters() {
+    let data = [2, 0, 0, 0, 3, 0, 0, 0, 1, 6, 0];
+
+    let verification = verify(&mut Cursor::new(data)).unwrap();
+
+    assert_eq!((verification.width, verification.height, verification.has_alpha), (2, 3, true));
+    assert!(verification.is_valid());
+}
+
+#[rstest]
+#[case::truncated_header(&[2, 0, 0, 0, 3], (5, None, IssueKind::TruncatedHeader))]
+#[case::invalid_alpha_flag(&[1, 0, 0, 0, 1, 0, 0, 0, 2, 1, 0], (8, None, IssueKind::InvalidAlphaFlag(2)))]
+#[case::zero_dimension(&[0, 0, 0, 0, 1, 0, 0, 0, 0], (0, None, IssueKind::ZeroDimension))]
+#[case::too_large(
+    &[0, 0, 1, 0, 0, 0, 1, 0, 0],
+    (0, None, IssueKind::TooLarge { width: 65536, height: 65536 }),
+)]
+#[case::zero_run_length(
+    &[2, 0, 0, 0, 1, 0, 0, 0, 0, 1, 1, 2, 3, 0, 1, 2, 3, 1, 1, 2, 3],
+    (13, Some(1), IssueKind::ZeroRunLength),
+)]
+#[case::run_overflow(
+    &[3, 0, 0, 0, 1, 0, 0, 0, 1, 2, 0, 2, 0],
+    (11, Some(2), IssueKind::RunOverflow { run_len: 2, remaining: 1 }),
+)]
+#[case::truncated_stream(
+    &[4, 0, 0, 0, 1, 0, 0, 0, 0, 1, 1, 2, 3, 1, 1],
+    (13, Some(1), IssueKind::TruncatedStream { missing_pixels: 3 }),
+)]
+#[case::trailing_bytes(
+    &[1, 0, 0, 0, 1, 0, 0, 0, 1, 1, 0, 7, 7, 7],
+    (11, None, IssueKind::TrailingBytes(3)),
+)]
+fn verify_reports_issue(#[case] data: &[u8], #[case] expected: (u64, Option<u64>, IssueKind)) {
+    let verification = verify(&mut Cursor::new(data)).unwrap();
+
+    let issue = &verification.issues[0];
+    assert_eq!((issue.offset, issue.pixel, &issue.kind), (expected.0, expected.1, &expected.2));
+}
+
+#[rstest]
+#[case::default_limits(DataLimits::default(), vec![IssueKind::TooLarge { width: 16385, height: 1 }])]
+#[case::raised_limits(DataLimits { max_dimension: 16385, ..Default::default() }, vec![])]
+fn verify_reports_image_exceeding_limits(#[case] limits: DataLimits, #[case] expected: Vec<IssueKind>) {
+    // Valid image of 16385x1 pixels, covered by 64 runs of 255 pixels and one run of 65 pixels
+    let mut data = vec![0x01, 0x40, 0, 0, 1, 0, 0, 0, 0];
+    data.extend([255, 1, 2, 3].repeat(64));
+    data.extend([65, 1, 2, 3]);
+
+    let verification = verify_with_limits(&mut Cursor::new(data), &limits).unwrap();
+
+    let kinds: Vec<IssueKind> = verification.issues.into_iter().map(|i| i.kind).collect();
+    assert_eq!(kinds, expected);
+}
+
+#[rstest]
+fn verify_reports_every_issue() {
+    let data = [2, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 3, 0, 9];
+
+    let verification = verify(&mut Cursor::new(data)).unwrap();
+
+    let kinds: Vec<&IssueKind> = verification.issues.iter().map(|i| &i.kind).collect();
+    assert_eq!(kinds, [
+        &IssueKind::InvalidAlphaFlag(3),
+        &IssueKind::ZeroRunLength,
+        &IssueKind::RunOverflow { run_len: 3, remaining: 2 },
+        &IssueKind::TrailingBytes(1),
+    ]);
+}
+
+#[rstest]
+fn verify_stops_after_too_many_issues() {
+    let mut data = vec![1, 0, 0, 0, 1, 0, 0, 0, 1];
+    data.extend([0; 1000]);
+
+    let verification = verify(&mut Cursor::new(data)).unwrap();
+
+    assert!(verification.issues.len() < 500);
+    assert_eq!(verification.issues.last().unwrap().kind, IssueKind::TooManyIssues);
+}
+
+#[rstest]
+fn issue_describes_position() {
+    let data = [2, 0, 0, 0, 1, 0, 0, 0, 1, 1, 0, 0, 0];
+
+    let verification = verify(&mut Cursor::new(data)).unwrap();
+
+    assert_eq!(verification.issues[0].to_string(), "byte 11, pixel 1: RLE count has value of 0");
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use celeste_converter::convert::{DataToPngOptions, PngToDataOptions};
+use celeste_converter::file::FileOptions;
+use celeste_converter::manifest::Manifest;
+use celeste_converter::watch::{watch, WatchOptions};
+use common::create_empty_dir;
+use rstest::rstest;
+use std::fs::{copy, create_dir_all, read, remove_file, rename, write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[rstest]
+fn watch_converts_existing_files() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/png/red.png", input.join("red.png")).unwrap();
+    copy("tests/data/blue.data", input.join("blue.data")).unwrap();
+
+    let watcher = Watcher::start(&input, &output);
+
+    assert!(wait_for(|| output.join("red.data").is_file() && output.join("blue.png").is_file()));
+    watcher.stop();
+}
+
+#[rstest]
+fn watch_converts_new_files() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    let watcher = Watcher::start(&input, &output);
+
+    create_dir_all(input.join("a")).unwrap();
+    copy("tests/png/red.png", input.join("a/red.png")).unwrap();
+
+    assert!(wait_for(|| output.join("a/red.data").is_file()));
+    watcher.stop();
+}
+
+#[rstest]
+fn watch_converts_changed_files() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/png/red.png", input.join("sprite.png")).unwrap();
+    let watcher = Watcher::start(&input, &output);
+    assert!(wait_for(|| output.join("sprite.data").is_file()));
+
+    copy("tests/png/blue.png", input.join("sprite.png")).unwrap();
+
+    let expected = read("tests/data/blue.data").unwrap();
+    assert!(wait_for(|| read(output.join("sprite.data")).is_ok_and(|d| d == expected)));
+    watcher.stop();
+}
+
+#[rstest]
+fn watch_removes_outputs_of_deleted_files() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/png/red.png", input.join("red.png")).unwrap();
+    let watcher = Watcher::start(&input, &output);
+    assert!(wait_for(|| output.join("red.data").is_file()));
+
+    remove_file(input.join("red.png")).unwrap();
+
+    assert!(wait_for(|| !output.join("red.data").exists()));
+    watcher.stop();
+}
+
+#[rstest]
+fn watch_moves_outputs_of_renamed_files() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/png/red.png", input.join("red.png")).unwrap();
+    let watcher = Watcher::start(&input, &output);
+    assert!(wait_for(|| output.join("red.data").is_file()));
+
+    create_dir_all(input.join("a")).unwrap();
+    rename(input.join("red.png"), input.join("a/renamed.png")).unwrap();
+
+    assert!(wait_for(|| output.join("a/renamed.data").is_file() && !output.join("red.data").exists()));
+    watcher.stop();
+}
+
+#[rstest]
+fn watch_records_converted_files_in_manifest() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    let watcher = Watcher::start(&input, &output);
+
+    copy("tests/png/red.png", input.join("red.png")).unwrap();
+
+    assert!(wait_for(|| Manifest::load(&output).get(Path::new("red.png")).is_some()));
+    watcher.stop();
+    let manifest = Manifest::load(&output);
+    assert_eq!(manifest.get(Path::new("red.png")).unwrap().output, PathBuf::from("red.data"));
+}
+
+#[rstest]
+fn watch_updates_manifest_of_renamed_and_deleted_files() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    copy("tests/png/red.png", input.join("red.png")).unwrap();
+    copy("tests/png/blue.png", input.join("blue.png")).unwrap();
+    let watcher = Watcher::start(&input, &output);
+    assert!(wait_for(|| output.join("red.data").is_file() && output.join("blue.data").is_file()));
+
+    rename(input.join("red.png"), input.join("renamed.png")).unwrap();
+    remove_file(input.join("blue.png")).unwrap();
+
+    assert!(wait_for(|| {
+        let manifest = Manifest::load(&output);
+        manifest.get(Path::new("renamed.png")).is_some() && manifest.get(Path::new("blue.png")).is_none()
+    }));
+    watcher.stop();
+    let manifest = Manifest::load(&output);
+    assert_eq!(manifest.get(Path::new("renamed.png")).unwrap().output, PathBuf::from("renamed.data"));
+    assert!(manifest.get(Path::new("red.png")).is_none());
+    assert!(manifest.get(Path::new("blue.png")).is_none());
+}
+
+#[rstest]
+fn watch_keeps_going_after_failure() {
+    let (input, output) = (create_empty_dir(), create_empty_dir());
+    let watcher = Watcher::start(&input, &output);
+
+    write(input.join("broken.png"), [1, 2, 3]).unwrap();
+    copy("tests/png/red.png", input.join("red.png")).unwrap();
+
+    assert!(wait_for(|| output.join("red.data").is_file()));
+    watcher.stop();
+}
+
+#[rstest]
+fn watch_fails_on_output_inside_input() {
+    let input = create_empty_dir();
+    let stop = AtomicBool::new(false);
+
+    let result = watch(&input, &input.join("out"), &DataToPngOptions::default(), &PngToDataOptions::default(), &FileOptions::default(), &fast_options(), &stop);
+
+    assert!(result.unwrap_err().to_string().contains("can't be inside"));
+}
+
+/// Watch running in the background.
+struct Watcher {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Watcher {
+    fn start(input: &Path, output: &Path) -> Watcher {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (input, output, thread_stop) = (input.to_path_buf(), output.to_path_buf(), stop.clone());
+        let handle = spawn(move || {
+            let (data_options, png_options) = (DataToPngOptions::default(), PngToDataOptions::default());
+            watch(&input, &output, &data_options, &png_options, &FileOptions::default(), &fast_options(), &thread_stop).unwrap();
+        });
+
+        // Changes made before the watch has started are picked up by the initial conversion anyway
+        sleep(Duration::from_millis(200));
+        Watcher { stop, handle }
+    }
+
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().unwrap();
+    }
+}
+
+fn fast_options() -> WatchOptions {
+    WatchOptions { poll_interval: Duration::from_millis(20), debounce: Duration::from_millis(50) }
+}
+
+fn wait_for<F: Fn() -> bool>(condition: F) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        sleep(Duration::from_millis(20));
+    }
+    false
+}